@@ -0,0 +1,281 @@
+use crate::span::{SourceMap, Span};
+use std::fmt::Write;
+
+// error codes
+pub(crate) const E_SYNTAX: &str = "E0001"; // unexpected or missing token
+pub(crate) const E_UNDEFINED: &str = "E0002"; // use of an undeclared name
+pub(crate) const E_CODEGEN: &str = "E0003"; // construct the backend cannot lower
+pub(crate) const E_TOKEN: &str = "E0004"; // character sequence that is not a token
+pub(crate) const E_IO: &str = "E0005"; // failure reading input or writing output
+pub(crate) const E_TYPE: &str = "E0006"; // operand of the wrong type
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[allow(dead_code)] // nothing produces warnings or notes yet
+pub(crate) enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+// a span with an explanation attached to it
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Label {
+    pub(crate) span: Span,
+    pub(crate) message: String,
+}
+
+// a suggested edit: replace the text under `span` with `replacement`
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct FixIt {
+    pub(crate) span: Span,
+    pub(crate) replacement: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Diagnostic {
+    pub(crate) severity: Severity,
+    pub(crate) code: &'static str,
+    pub(crate) message: String,
+    pub(crate) primary: Label,
+    pub(crate) secondary: Vec<Label>,
+    pub(crate) notes: Vec<String>,
+    pub(crate) fixits: Vec<FixIt>,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(severity: Severity, code: &'static str, message: &str, span: Span) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.to_string(),
+            primary: Label {
+                span,
+                message: String::new(),
+            },
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            fixits: Vec::new(),
+        }
+    }
+
+    pub(crate) fn error(code: &'static str, message: &str, span: Span) -> Self {
+        Self::new(Severity::Error, code, message, span)
+    }
+
+    // text printed under the primary caret
+    pub(crate) fn with_label(mut self, message: &str) -> Self {
+        self.primary.message = message.to_string();
+        self
+    }
+
+    pub(crate) fn with_secondary(mut self, span: Span, message: &str) -> Self {
+        self.secondary.push(Label {
+            span,
+            message: message.to_string(),
+        });
+        self
+    }
+
+    pub(crate) fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub(crate) fn with_fixit(mut self, span: Span, replacement: &str) -> Self {
+        self.fixits.push(FixIt {
+            span,
+            replacement: replacement.to_string(),
+        });
+        self
+    }
+
+    // render in the rustc style:
+    //
+    // error[E0001]: expected semicolon
+    //  --> main.c:1:20
+    //   |
+    // 1 | int main(){return 0}
+    //   |                    ^ expected `;`
+    //   |
+    //   = help: insert `;`
+    pub(crate) fn render(&self, sources: &SourceMap) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}[{}]: {}",
+            self.severity.as_str(),
+            self.code,
+            self.message
+        );
+        // lines are numbered from 1, so line 0 marks a diagnostic that has
+        // no place in the source (e.g. an output error)
+        if self.primary.span.line == 0 {
+            return out;
+        }
+        let file = match sources.get(self.primary.span.file) {
+            Some(file) => file,
+            None => return out,
+        };
+
+        let mut labels = vec![(&self.primary, '^')];
+        labels.extend(self.secondary.iter().map(|label| (label, '-')));
+        labels.sort_by_key(|(label, _)| label.span.offset);
+        let width = labels
+            .iter()
+            .map(|(label, _)| label.span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(width);
+
+        let _ = writeln!(
+            out,
+            "{}--> {}:{}:{}",
+            gutter, file.name, self.primary.span.line, self.primary.span.column
+        );
+        let _ = writeln!(out, "{} |", gutter);
+        let mut last_line = None;
+        for (label, mark) in labels {
+            let span = label.span;
+            let text = match sources.get(span.file).and_then(|f| f.line(span.line)) {
+                Some(text) => text,
+                None => continue,
+            };
+            // labels on the same line share one copy of the source text
+            if last_line != Some((span.file, span.line)) {
+                let _ = writeln!(out, "{:>width$} | {}", span.line, text, width = width);
+                last_line = Some((span.file, span.line));
+            }
+            let underline = underline_width(text, span);
+            let mut marker = format!(
+                "{}{}",
+                " ".repeat(span.column.saturating_sub(1)),
+                mark.to_string().repeat(underline)
+            );
+            if !label.message.is_empty() {
+                marker.push(' ');
+                marker.push_str(&label.message);
+            }
+            let _ = writeln!(out, "{} | {}", gutter, marker);
+        }
+        if !self.notes.is_empty() || !self.fixits.is_empty() {
+            let _ = writeln!(out, "{} |", gutter);
+        }
+        for note in &self.notes {
+            let _ = writeln!(out, "{} = note: {}", gutter, note);
+        }
+        for fixit in &self.fixits {
+            let _ = writeln!(out, "{} = help: {}", gutter, fixit.describe(sources));
+        }
+        out
+    }
+}
+
+impl FixIt {
+    fn describe(&self, sources: &SourceMap) -> String {
+        let original = sources
+            .get(self.span.file)
+            .and_then(|file| file.src.get(self.span.offset..self.span.end()))
+            .unwrap_or("");
+        if original.is_empty() {
+            format!("insert `{}`", self.replacement)
+        } else if self.replacement.is_empty() {
+            format!("remove `{}`", original)
+        } else {
+            format!("replace `{}` with `{}`", original, self.replacement)
+        }
+    }
+}
+
+// how many characters of `line` the span covers, at least one
+fn underline_width(line: &str, span: Span) -> usize {
+    let mut bytes = 0;
+    let mut width = 0;
+    for c in line.chars().skip(span.column.saturating_sub(1)) {
+        if bytes >= span.len {
+            break;
+        }
+        bytes += c.len_utf8();
+        width += 1;
+    }
+    width.max(1)
+}
+
+// render a batch of diagnostics back to back
+pub(crate) fn render_all(diagnostics: &[Diagnostic], sources: &SourceMap) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.render(sources))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+//------------------------------------------------------------------------
+
+#[cfg(test)]
+#[test]
+fn render_test() {
+    let mut sources = SourceMap::new();
+    let file = sources.add("main.c".to_string(), "int main(){return 0}".to_string());
+    let span = Span::new(file, 19, 0, 1, 20);
+    let diagnostic = Diagnostic::error(E_SYNTAX, "expected semicolon", span)
+        .with_label("expected `;`")
+        .with_fixit(span, ";");
+    let expected = "error[E0001]: expected semicolon
+ --> main.c:1:20
+  |
+1 | int main(){return 0}
+  |                    ^ expected `;`
+  |
+  = help: insert `;`
+";
+    assert_eq!(diagnostic.render(&sources), expected);
+}
+
+#[test]
+fn same_line_test() {
+    let mut sources = SourceMap::new();
+    let file = sources.add("a.c".to_string(), "(1 + 2;".to_string());
+    let diagnostic =
+        Diagnostic::error(E_SYNTAX, "invalid parentheses", Span::new(file, 6, 0, 1, 7))
+            .with_label("expected `)`")
+            .with_secondary(Span::new(file, 0, 1, 1, 1), "to match this `(`");
+    let expected = "error[E0001]: invalid parentheses
+ --> a.c:1:7
+  |
+1 | (1 + 2;
+  | - to match this `(`
+  |       ^ expected `)`
+";
+    assert_eq!(diagnostic.render(&sources), expected);
+}
+
+#[test]
+fn secondary_test() {
+    let mut sources = SourceMap::new();
+    let file = sources.add("a.c".to_string(), "int a;\nb = 1;".to_string());
+    let span = Span::new(file, 7, 1, 2, 1);
+    let diagnostic = Diagnostic::error(E_UNDEFINED, "use of undeclared variable `b`", span)
+        .with_secondary(Span::new(file, 4, 1, 1, 5), "`a` declared here")
+        .with_note("variables must be declared before use");
+    let expected = "error[E0002]: use of undeclared variable `b`
+ --> a.c:2:1
+  |
+1 | int a;
+  |     - `a` declared here
+2 | b = 1;
+  | ^
+  |
+  = note: variables must be declared before use
+";
+    assert_eq!(diagnostic.render(&sources), expected);
+}
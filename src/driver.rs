@@ -0,0 +1,434 @@
+use crate::diagnostic::render_all;
+use crate::rcc::Rcc;
+use crate::span::SourceMap;
+use crate::target::Target;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const USAGE: &str = "usage: rcc [options] <file>...
+
+  <file>      C source file to compile, or `-` to read from stdin;
+              `.s` files are assembled, `.o`/`.a` files are linked as is
+
+options:
+  -S          only compile, write assembly to <file>.s
+              (stdout for stdin input)
+  -c          compile and assemble, write an object to <file>.o
+  -o <path>   write output to <path> (`-` for stdout with -S);
+              the default executable is a.out
+  -L <dir>    add <dir> to the linker search path
+  -l <lib>    link against <lib>
+  --target <triple>
+              generate code for x86_64-linux-gnu or x86_64-apple-darwin
+              (default: the host)
+  -h, --help  print this message
+  --          treat every following argument as an input file
+";
+
+// the program that assembles and links for us
+const TOOLCHAIN: &str = "cc";
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Input {
+    File(String),
+    Stdin,
+}
+
+impl Input {
+    // the name diagnostics refer to this input by
+    fn name(&self) -> &str {
+        match self {
+            Input::File(path) => path,
+            Input::Stdin => "<stdin>",
+        }
+    }
+
+    fn extension(&self) -> Option<&str> {
+        match self {
+            Input::File(path) => Path::new(path).extension()?.to_str(),
+            Input::Stdin => None,
+        }
+    }
+
+    // objects and archives go straight to the linker
+    fn is_linker_input(&self) -> bool {
+        matches!(self.extension(), Some("o" | "a" | "so"))
+    }
+
+    // the input's file name with a new extension, in the current directory
+    fn output_name(&self, extension: &str) -> Option<String> {
+        match self {
+            Input::File(path) => {
+                let stem = Path::new(path).file_stem()?.to_string_lossy();
+                Some(format!("{}.{}", stem, extension))
+            }
+            Input::Stdin => None,
+        }
+    }
+
+    fn read(&self) -> Result<String, String> {
+        match self {
+            Input::File(path) => {
+                fs::read_to_string(path).map_err(|err| format!("cannot read `{}`: {}", path, err))
+            }
+            Input::Stdin => {
+                let mut src = String::new();
+                io::stdin()
+                    .read_to_string(&mut src)
+                    .map_err(|err| format!("cannot read stdin: {}", err))?;
+                Ok(src)
+            }
+        }
+    }
+}
+
+// how far to take the inputs
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub(crate) enum Stage {
+    Assembly, // -S
+    Object,   // -c
+    #[default]
+    Executable,
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub(crate) struct Options {
+    pub(crate) inputs: Vec<Input>,
+    pub(crate) output: Option<String>, // -o
+    pub(crate) stage: Stage,
+    pub(crate) lib_paths: Vec<String>, // -L
+    pub(crate) libs: Vec<String>,      // -l
+    pub(crate) target: Target,
+    pub(crate) help: bool,
+}
+
+impl Options {
+    // `args` excludes the program name
+    pub(crate) fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut only_inputs = false;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if only_inputs {
+                options.inputs.push(Input::File(arg));
+                continue;
+            }
+            match arg.as_str() {
+                "-h" | "--help" => options.help = true,
+                "--" => only_inputs = true,
+                "-" => options.inputs.push(Input::Stdin),
+                "-S" => options.stage = Stage::Assembly,
+                "-c" => options.stage = Stage::Object,
+                "-o" | "-L" | "-l" | "--target" => match args.next() {
+                    Some(value) => options.set(&arg, value)?,
+                    None => return Err(format!("missing argument after `{}`", arg)),
+                },
+                _ if arg.starts_with("--target=") => {
+                    options.set("--target", arg["--target=".len()..].to_string())?
+                }
+                _ if arg.starts_with("-o") || arg.starts_with("-L") || arg.starts_with("-l") => {
+                    let (flag, value) = arg.split_at(2);
+                    options.set(flag, value.to_string())?;
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+                _ => options.inputs.push(Input::File(arg)),
+            }
+        }
+        if options.help {
+            return Ok(options);
+        }
+        if options.inputs.is_empty() {
+            return Err("no input file".to_string());
+        }
+        let sources = options
+            .inputs
+            .iter()
+            .filter(|input| !input.is_linker_input())
+            .count();
+        if options.output.is_some() && options.stage != Stage::Executable && sources > 1 {
+            return Err("cannot use `-o` with `-S` or `-c` and several input files".to_string());
+        }
+        Ok(options)
+    }
+
+    fn set(&mut self, flag: &str, value: String) -> Result<(), String> {
+        match flag {
+            "-o" => self.output = Some(value),
+            "-L" => self.lib_paths.push(value),
+            "-l" => self.libs.push(value),
+            _ => match Target::parse(&value) {
+                Some(target) => self.target = target,
+                None => {
+                    return Err(format!(
+                        "unsupported target `{}`, expected `{}` or `{}`",
+                        value,
+                        Target::LinuxGnu.triple(),
+                        Target::AppleDarwin.triple()
+                    ))
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // where the result for `input` goes when stopping before the link;
+    // `None` means stdout
+    fn output_path(&self, input: &Input, extension: &str) -> Result<Option<String>, String> {
+        match (&self.output, input) {
+            (Some(path), _) if path == "-" && self.stage == Stage::Assembly => Ok(None),
+            (Some(path), _) => Ok(Some(path.clone())),
+            (None, Input::File(_)) => Ok(input.output_name(extension)),
+            (None, Input::Stdin) if self.stage == Stage::Assembly => Ok(None),
+            (None, Input::Stdin) => Err("`-o` is required to compile stdin with `-c`".to_string()),
+        }
+    }
+}
+
+// a directory for intermediate files, removed when dropped
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new() -> Result<Self, String> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "rcc-{}-{}",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = env::temp_dir().join(name);
+        fs::create_dir_all(&path)
+            .map_err(|err| format!("cannot create `{}`: {}", path.display(), err))?;
+        Ok(Self { path })
+    }
+
+    fn file(&self, name: &str) -> String {
+        self.path.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+fn create(path: &Option<String>) -> Result<Box<dyn Write>, String> {
+    match path {
+        None => Ok(Box::new(BufWriter::new(io::stdout()))),
+        Some(path) => {
+            let file =
+                File::create(path).map_err(|err| format!("cannot create `{}`: {}", path, err))?;
+            Ok(Box::new(BufWriter::new(file)))
+        }
+    }
+}
+
+// remove a partial output, but never a device or anything else that is not
+// an ordinary file (`-o /dev/null` must stay intact)
+fn remove_output(path: &str) {
+    if fs::metadata(path)
+        .map(|meta| meta.is_file())
+        .unwrap_or(false)
+    {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn error(message: String) -> String {
+    format!("rcc: error: {}\n", message)
+}
+
+// compile one C input into assembly at `path` (stdout if `None`)
+fn compile(
+    input: &Input,
+    path: &Option<String>,
+    target: Target,
+    sources: &mut SourceMap,
+) -> Result<(), String> {
+    let src = input.read().map_err(error)?;
+    let file = sources.add(input.name().to_string(), src.clone());
+    let out = create(path).map_err(error)?;
+    let mut rcc = Rcc::init(src, file, out, target);
+    if let Err(diagnostics) = rcc.compile() {
+        // don't leave a half-written file behind
+        if let Some(path) = path {
+            remove_output(path);
+        }
+        return Err(render_all(&diagnostics, sources));
+    }
+    Ok(())
+}
+
+// run one toolchain step, turning any failure into a readable error
+fn invoke(step: &str, command: &mut Command) -> Result<(), String> {
+    let status = command.status().map_err(|err| {
+        error(format!(
+            "cannot run `{}` as the {}: {}",
+            TOOLCHAIN, step, err
+        ))
+    })?;
+    if status.success() {
+        return Ok(());
+    }
+    let args = command
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Err(error(format!(
+        "{} failed: `{} {}` exited with {}",
+        step, TOOLCHAIN, args, status
+    )))
+}
+
+fn assemble(asm: &str, object: &str) -> Result<(), String> {
+    let mut command = Command::new(TOOLCHAIN);
+    command.args(["-c", "-x", "assembler", asm, "-o", object]);
+    invoke("assembler", &mut command)
+}
+
+fn link(objects: &[String], output: &str, options: &Options) -> Result<(), String> {
+    let mut command = Command::new(TOOLCHAIN);
+    command.args(objects).arg("-o").arg(output);
+    for path in &options.lib_paths {
+        command.arg(format!("-L{}", path));
+    }
+    for lib in &options.libs {
+        command.arg(format!("-l{}", lib));
+    }
+    invoke("linker", &mut command)
+}
+
+// on failure, returns the report to print on stderr
+pub(crate) fn run<I: IntoIterator<Item = String>>(args: I) -> Result<(), String> {
+    let options = Options::parse(args).map_err(|err| format!("{}{}", error(err), USAGE))?;
+    if options.help {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+
+    let temp = TempDir::new().map_err(error)?;
+    let mut sources = SourceMap::new();
+    let mut objects = Vec::new();
+    for (index, input) in options.inputs.iter().enumerate() {
+        if input.is_linker_input() {
+            objects.push(input.name().to_string());
+            continue;
+        }
+
+        let asm = if input.extension() == Some("s") {
+            input.name().to_string()
+        } else {
+            let path = match options.stage {
+                Stage::Assembly => options.output_path(input, "s").map_err(error)?,
+                _ => Some(temp.file(&format!("{}.s", index))),
+            };
+            compile(input, &path, options.target, &mut sources)?;
+            match path {
+                Some(path) => path,
+                None => continue, // the assembly went to stdout
+            }
+        };
+
+        let object = match options.stage {
+            Stage::Assembly => continue,
+            Stage::Object => match options.output_path(input, "o").map_err(error)? {
+                Some(path) => path,
+                None => continue,
+            },
+            Stage::Executable => temp.file(&format!("{}.o", index)),
+        };
+        assemble(&asm, &object)?;
+        objects.push(object);
+    }
+
+    if options.stage == Stage::Executable {
+        let output = options.output.as_deref().unwrap_or("a.out");
+        link(&objects, output, &options)?;
+    }
+    Ok(())
+}
+
+//------------------------------------------------------------------------
+
+#[cfg(test)]
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn file_test() {
+    let options = Options::parse(args(&["foo.c"])).unwrap();
+    assert_eq!(options.inputs, vec![Input::File("foo.c".to_string())]);
+    assert_eq!(options.stage, Stage::Executable);
+}
+
+#[test]
+fn stdin_test() {
+    let options = Options::parse(args(&["-"])).unwrap();
+    assert_eq!(options.inputs, vec![Input::Stdin]);
+}
+
+#[test]
+fn option_test() {
+    assert!(Options::parse(args(&["-x", "foo.c"])).is_err());
+    assert!(Options::parse(args(&[])).is_err());
+    assert!(Options::parse(args(&["--help"])).unwrap().help);
+    let options = Options::parse(args(&["--", "-foo.c"])).unwrap();
+    assert_eq!(options.inputs, vec![Input::File("-foo.c".to_string())]);
+}
+
+#[test]
+fn output_test() {
+    let options = Options::parse(args(&["-S", "dir/foo.c"])).unwrap();
+    let input = &options.inputs[0];
+    assert_eq!(
+        options.output_path(input, "s"),
+        Ok(Some("foo.s".to_string()))
+    );
+    let options = Options::parse(args(&["-S", "-"])).unwrap();
+    assert_eq!(options.output_path(&options.inputs[0], "s"), Ok(None));
+    let options = Options::parse(args(&["-c", "-"])).unwrap();
+    assert!(options.output_path(&options.inputs[0], "o").is_err());
+    let options = Options::parse(args(&["-S", "foo.c", "-o", "out.s"])).unwrap();
+    assert_eq!(
+        options.output_path(&options.inputs[0], "s"),
+        Ok(Some("out.s".to_string()))
+    );
+    let options = Options::parse(args(&["-obar", "foo.c"])).unwrap();
+    assert_eq!(options.output, Some("bar".to_string()));
+    assert!(Options::parse(args(&["foo.c", "-o"])).is_err());
+    assert!(Options::parse(args(&["-c", "-o", "x.o", "a.c", "b.c"])).is_err());
+}
+
+#[test]
+fn link_option_test() {
+    let options = Options::parse(args(&[
+        "a.c", "b.o", "-L", "lib", "-Lusr", "-lm", "-l", "c",
+    ]))
+    .unwrap();
+    assert_eq!(
+        options.lib_paths,
+        vec!["lib".to_string(), "usr".to_string()]
+    );
+    assert_eq!(options.libs, vec!["m".to_string(), "c".to_string()]);
+    assert!(options.inputs[1].is_linker_input());
+    assert!(!options.inputs[0].is_linker_input());
+}
+
+#[test]
+fn target_test() {
+    let options = Options::parse(args(&["a.c"])).unwrap();
+    assert_eq!(options.target, Target::host());
+    let options = Options::parse(args(&["--target", "x86_64-apple-darwin", "a.c"])).unwrap();
+    assert_eq!(options.target, Target::AppleDarwin);
+    let options = Options::parse(args(&["--target=x86_64-linux-gnu", "a.c"])).unwrap();
+    assert_eq!(options.target, Target::LinuxGnu);
+    assert!(Options::parse(args(&["--target=arm", "a.c"])).is_err());
+}
@@ -1,6 +1,14 @@
+use crate::span::Span;
+use std::fmt;
+
 pub(crate) struct Lexer {
     cursor: usize,
     characters: Vec<char>,
+    file: usize,
+    offset: usize, // byte offset of the cursor
+    line: usize,
+    column: usize,
+    start: Span, // where the current token begins
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -16,80 +24,192 @@ pub(crate) enum TokenKind {
     NotEq,
     DoubleEq,
     Eq,
-    OpenParen,  // (
-    CloseParen, // )
-    OpenCur,    // {
-    CloseCur,   // }
+    OpenParen,   // (
+    CloseParen,  // )
+    OpenCur,     // {
+    CloseCur,    // }
+    OpenSquare,  // [
+    CloseSquare, // ]
     Comma,
     SemiCol,
-    And, // &
+    Colon,
+    Dot,      // .
+    Ellipsis, // ...
+    Arrow,    // ->
+    And,      // &
     Return,
     If,
     For,
     While,
     Else,
+    Switch,
+    Case,
+    Default,
+    Break,
     Int,
+    Char,
+    Short,
+    Long,
+    Signed,
+    Unsigned,
+    Struct,
+    Union,
+    Enum,
+    Typedef,
+    Void, // only as the empty parameter list `(void)`
     Num(String),
+    Character(i64), // value of a character literal
+    Str(Vec<u8>),   // contents of a string literal, escapes resolved
     Ident(String),
+    Error(String), // malformed input, with a message saying why
 }
 
 #[derive(Debug, PartialEq)]
 pub(crate) struct Token {
     pub(crate) kind: TokenKind,
-    pub(crate) len: usize,
+    pub(crate) span: Span,
 }
 
 impl Token {
-    pub(crate) fn new(kind: TokenKind, len: usize) -> Self {
-        Self { kind, len }
+    pub(crate) fn new(kind: TokenKind, span: Span) -> Self {
+        Self { kind, span }
     }
-    pub(crate) fn sym(kind: TokenKind, len: usize) -> Option<Box<Self>> {
-        Some(Box::new(Self::new(kind, len)))
+    pub(crate) fn sym(kind: TokenKind, span: Span) -> Option<Box<Self>> {
+        Some(Box::new(Self::new(kind, span)))
     }
 
-    pub(crate) fn num(state: Vec<char>) -> Option<Box<Self>> {
-        let len = state.len();
+    pub(crate) fn num(state: Vec<char>, span: Span) -> Option<Box<Self>> {
         Some(Box::new(Self::new(
             TokenKind::Num(state.into_iter().collect()),
-            len,
+            span,
         )))
     }
 
-    pub(crate) fn word(state: Vec<char>) -> Option<Box<Self>> {
-        let len = state.len();
+    pub(crate) fn word(state: Vec<char>, span: Span) -> Option<Box<Self>> {
         let word = state.into_iter().collect::<String>();
         match word.as_str() {
-            "int" => Some(Box::new(Self::new(TokenKind::Int, len))),
-            "else" => Some(Box::new(Self::new(TokenKind::Else, len))),
-            "if" => Some(Box::new(Self::new(TokenKind::If, len))),
-            "while" => Some(Box::new(Self::new(TokenKind::While, len))),
-            "for" => Some(Box::new(Self::new(TokenKind::For, len))),
-            "return" => Some(Box::new(Self::new(TokenKind::Return, len))),
-            _ => Some(Box::new(Self::new(TokenKind::Ident(word), len))),
+            "int" => Some(Box::new(Self::new(TokenKind::Int, span))),
+            "char" => Some(Box::new(Self::new(TokenKind::Char, span))),
+            "short" => Some(Box::new(Self::new(TokenKind::Short, span))),
+            "long" => Some(Box::new(Self::new(TokenKind::Long, span))),
+            "signed" => Some(Box::new(Self::new(TokenKind::Signed, span))),
+            "unsigned" => Some(Box::new(Self::new(TokenKind::Unsigned, span))),
+            "struct" => Some(Box::new(Self::new(TokenKind::Struct, span))),
+            "union" => Some(Box::new(Self::new(TokenKind::Union, span))),
+            "enum" => Some(Box::new(Self::new(TokenKind::Enum, span))),
+            "typedef" => Some(Box::new(Self::new(TokenKind::Typedef, span))),
+            "void" => Some(Box::new(Self::new(TokenKind::Void, span))),
+            "switch" => Some(Box::new(Self::new(TokenKind::Switch, span))),
+            "case" => Some(Box::new(Self::new(TokenKind::Case, span))),
+            "default" => Some(Box::new(Self::new(TokenKind::Default, span))),
+            "break" => Some(Box::new(Self::new(TokenKind::Break, span))),
+            "else" => Some(Box::new(Self::new(TokenKind::Else, span))),
+            "if" => Some(Box::new(Self::new(TokenKind::If, span))),
+            "while" => Some(Box::new(Self::new(TokenKind::While, span))),
+            "for" => Some(Box::new(Self::new(TokenKind::For, span))),
+            "return" => Some(Box::new(Self::new(TokenKind::Return, span))),
+            _ => Some(Box::new(Self::new(TokenKind::Ident(word), span))),
         }
     }
 }
 
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            TokenKind::Add => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Star => "*",
+            TokenKind::Slash => "/",
+            TokenKind::Lt => "<",
+            TokenKind::Gt => ">",
+            TokenKind::Geq => ">=",
+            TokenKind::Leq => "<=",
+            TokenKind::NotEq => "!=",
+            TokenKind::DoubleEq => "==",
+            TokenKind::Eq => "=",
+            TokenKind::OpenParen => "(",
+            TokenKind::CloseParen => ")",
+            TokenKind::OpenCur => "{",
+            TokenKind::CloseCur => "}",
+            TokenKind::OpenSquare => "[",
+            TokenKind::CloseSquare => "]",
+            TokenKind::Comma => ",",
+            TokenKind::SemiCol => ";",
+            TokenKind::Colon => ":",
+            TokenKind::Dot => ".",
+            TokenKind::Ellipsis => "...",
+            TokenKind::Arrow => "->",
+            TokenKind::And => "&",
+            TokenKind::Return => "return",
+            TokenKind::If => "if",
+            TokenKind::For => "for",
+            TokenKind::While => "while",
+            TokenKind::Else => "else",
+            TokenKind::Switch => "switch",
+            TokenKind::Case => "case",
+            TokenKind::Default => "default",
+            TokenKind::Break => "break",
+            TokenKind::Int => "int",
+            TokenKind::Char => "char",
+            TokenKind::Short => "short",
+            TokenKind::Long => "long",
+            TokenKind::Signed => "signed",
+            TokenKind::Unsigned => "unsigned",
+            TokenKind::Struct => "struct",
+            TokenKind::Union => "union",
+            TokenKind::Enum => "enum",
+            TokenKind::Typedef => "typedef",
+            TokenKind::Void => "void",
+            TokenKind::Num(num) => num,
+            TokenKind::Character(_) => return write!(f, "character literal"),
+            TokenKind::Str(_) => return write!(f, "string literal"),
+            TokenKind::Ident(name) => name,
+            TokenKind::Error(_) => return write!(f, "invalid token"),
+        };
+        write!(f, "`{}`", text)
+    }
+}
+
 impl Iterator for Lexer {
     type Item = Box<Token>;
     fn next(&mut self) -> Option<Self::Item> {
         loop {
+            self.start = Span::new(self.file, self.offset, 0, self.line, self.column);
             match self.first() {
                 None => return None,
                 Some(&c) => match c {
+                    ' ' | '\t' | '\n' | '\r' => self.advance(),
                     '0'..='9' => return self.num(),
-                    'a'..='z' | 'A'..='Z' => return self.word(),
+                    'a'..='z' | 'A'..='Z' | '_' => return self.word(),
+                    '\'' => return self.character(),
+                    '"' => return self.string(),
                     '&' => return self.bump(TokenKind::And, 1),
                     ',' => return self.bump(TokenKind::Comma, 1),
                     '{' => return self.bump(TokenKind::OpenCur, 1),
                     '}' => return self.bump(TokenKind::CloseCur, 1),
+                    '[' => return self.bump(TokenKind::OpenSquare, 1),
+                    ']' => return self.bump(TokenKind::CloseSquare, 1),
                     '(' => return self.bump(TokenKind::OpenParen, 1),
                     ')' => return self.bump(TokenKind::CloseParen, 1),
                     '+' => return self.bump(TokenKind::Add, 1),
-                    '-' => return self.bump(TokenKind::Minus, 1),
+                    '-' => {
+                        if let Some('>') = self.second() {
+                            return self.bump(TokenKind::Arrow, 2);
+                        }
+                        return self.bump(TokenKind::Minus, 1);
+                    }
+                    '.' => {
+                        if let (Some('.'), Some('.')) =
+                            (self.second().copied(), self.third().copied())
+                        {
+                            return self.bump(TokenKind::Ellipsis, 3);
+                        }
+                        return self.bump(TokenKind::Dot, 1);
+                    }
                     '*' => return self.bump(TokenKind::Star, 1),
                     '/' => return self.bump(TokenKind::Slash, 1),
                     ';' => return self.bump(TokenKind::SemiCol, 1),
+                    ':' => return self.bump(TokenKind::Colon, 1),
                     '>' => {
                         if let Some('=') = self.second() {
                             return self.bump(TokenKind::Geq, 2);
@@ -112,9 +232,13 @@ impl Iterator for Lexer {
                         if let Some('=') = self.second() {
                             return self.bump(TokenKind::NotEq, 2);
                         }
-                        panic!("Expected `=`");
+                        let message = "expected `=` after `!`".to_string();
+                        return self.bump(TokenKind::Error(message), 1);
+                    }
+                    _ => {
+                        let message = format!("unknown character `{}`", c.escape_debug());
+                        return self.bump(TokenKind::Error(message), 1);
                     }
-                    _ => self.advance(),
                 },
             }
         }
@@ -122,10 +246,15 @@ impl Iterator for Lexer {
 }
 
 impl Lexer {
-    pub(crate) fn new(src: String) -> Self {
+    pub(crate) fn with_file(src: String, file: usize) -> Self {
         Self {
             cursor: 0,
             characters: src.chars().collect(),
+            file,
+            offset: 0,
+            line: 1,
+            column: 1,
+            start: Span::new(file, 0, 0, 1, 1),
         }
     }
 
@@ -133,13 +262,13 @@ impl Lexer {
         let mut state: Vec<char> = Vec::new();
         loop {
             match self.first() {
-                None => return Token::num(state),
+                None => return Token::num(state, self.span()),
                 Some(&c) => match c {
                     '0'..='9' => {
                         state.push(c);
-                        self.cursor += 1;
+                        self.advance();
                     }
-                    _ => return Token::num(state),
+                    _ => return Token::num(state, self.span()),
                 },
             }
         }
@@ -148,30 +277,163 @@ impl Lexer {
         let mut state: Vec<char> = Vec::new();
         loop {
             match self.first() {
-                None => return Token::word(state),
+                None => return Token::word(state, self.span()),
                 Some(&c) => match c {
-                    'a'..='z' | 'A'..='Z'| '0'..='9' => {
+                    'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => {
                         state.push(c);
-                        self.cursor += 1;
+                        self.advance();
                     }
-                    _ => return Token::word(state),
+                    _ => return Token::word(state, self.span()),
+                },
+            }
+        }
+    }
+
+    // 'a' or '\n'; its value is that of the byte as a (signed) char
+    fn character(&mut self) -> Option<Box<Token>> {
+        let kind = match self.quoted('\'', "character") {
+            Err(message) => TokenKind::Error(message),
+            Ok(bytes) => match bytes.as_slice() {
+                [] => TokenKind::Error("empty character literal".to_string()),
+                [byte] => TokenKind::Character(*byte as i8 as i64),
+                _ => {
+                    TokenKind::Error("character literal may only contain one character".to_string())
+                }
+            },
+        };
+        Token::sym(kind, self.span())
+    }
+
+    // "..." on a single line
+    fn string(&mut self) -> Option<Box<Token>> {
+        let kind = match self.quoted('"', "string") {
+            Ok(bytes) => TokenKind::Str(bytes),
+            Err(message) => TokenKind::Error(message),
+        };
+        Token::sym(kind, self.span())
+    }
+
+    // the bytes between a pair of `quote`s, escapes resolved; `what` names
+    // the literal in errors
+    fn quoted(&mut self, quote: char, what: &str) -> Result<Vec<u8>, String> {
+        self.advance();
+        let mut bytes = Vec::new();
+        let mut error = None;
+        loop {
+            match self.first() {
+                None | Some('\n') => return Err(format!("unterminated {} literal", what)),
+                Some(&c) if c == quote => {
+                    self.advance();
+                    break;
+                }
+                Some('\\') => match self.escape() {
+                    Ok(byte) => bytes.push(byte),
+                    Err(message) => error = error.or(Some(message)),
                 },
+                Some(&c) => {
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    self.advance();
+                }
             }
         }
+        match error {
+            Some(message) => Err(message),
+            None => Ok(bytes),
+        }
+    }
+
+    // the byte an escape sequence at the cursor stands for
+    fn escape(&mut self) -> Result<u8, String> {
+        self.advance();
+        let c = match self.first() {
+            Some(&c) => c,
+            None => return Err("unterminated escape sequence".to_string()),
+        };
+        self.advance();
+        let byte = match c {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            'e' => 0x1b,
+            '\\' | '\'' | '"' | '?' => c as u8,
+            // up to three octal digits
+            '0'..='7' => {
+                let mut value = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match self.first().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            self.advance();
+                        }
+                        None => break,
+                    }
+                }
+                if value > 0xff {
+                    return Err("octal escape sequence out of range".to_string());
+                }
+                value as u8
+            }
+            'x' => {
+                let mut value: u32 = 0;
+                let mut digits = 0;
+                while let Some(digit) = self.first().and_then(|c| c.to_digit(16)) {
+                    value = value.saturating_mul(16).saturating_add(digit);
+                    digits += 1;
+                    self.advance();
+                }
+                if digits == 0 {
+                    return Err("`\\x` used with no following hex digits".to_string());
+                }
+                if value > 0xff {
+                    return Err("hex escape sequence out of range".to_string());
+                }
+                value as u8
+            }
+            _ => return Err(format!("unknown escape sequence `\\{}`", c.escape_debug())),
+        };
+        Ok(byte)
     }
 
     fn bump(&mut self, kind: TokenKind, len: usize) -> Option<Box<Token>> {
-        self.cursor += len;
-        Token::sym(kind, len)
+        for _ in 0..len {
+            self.advance();
+        }
+        Token::sym(kind, self.span())
+    }
+
+    // the span from the start of the current token up to the cursor
+    fn span(&self) -> Span {
+        Span {
+            len: self.offset - self.start.offset,
+            ..self.start
+        }
     }
 
     fn advance(&mut self) {
+        if let Some(&c) = self.first() {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
         self.cursor += 1;
     }
     fn second(&mut self) -> Option<&char> {
         self.characters.get(self.cursor + 1)
     }
 
+    fn third(&self) -> Option<&char> {
+        self.characters.get(self.cursor + 2)
+    }
+
     fn first(&self) -> Option<&char> {
         self.characters.get(self.cursor)
     }
@@ -180,6 +442,12 @@ impl Lexer {
 //------------------------------------------------------------------------
 
 #[cfg(test)]
+impl Lexer {
+    pub(crate) fn new(src: String) -> Self {
+        Self::with_file(src, 0)
+    }
+}
+
 #[test]
 fn add_test() {
     let code = String::from("42 + 31");
@@ -295,3 +563,153 @@ fn block_test() {
     assert_eq!(lexer.next().unwrap().kind, TokenKind::SemiCol);
     assert_eq!(lexer.next().unwrap().kind, TokenKind::CloseCur);
 }
+
+#[test]
+fn square_test() {
+    let code = String::from("a[1]");
+    let mut lexer = Lexer::new(code);
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        TokenKind::Ident("a".to_string())
+    );
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::OpenSquare);
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Num("1".to_string()));
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::CloseSquare);
+}
+
+#[test]
+fn member_test() {
+    let code = String::from("struct s.a->b - >");
+    let mut lexer = Lexer::new(code);
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Struct);
+    lexer.next();
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Dot);
+    lexer.next();
+    let arrow = lexer.next().unwrap();
+    assert_eq!(arrow.kind, TokenKind::Arrow);
+    assert_eq!(arrow.span.len, 2);
+    lexer.next();
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Minus);
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Gt);
+}
+
+#[test]
+fn ellipsis_test() {
+    let code = String::from("(int, ...) ..");
+    let mut lexer = Lexer::new(code);
+    lexer.next();
+    lexer.next();
+    lexer.next();
+    let ellipsis = lexer.next().unwrap();
+    assert_eq!(ellipsis.kind, TokenKind::Ellipsis);
+    assert_eq!(ellipsis.span.len, 3);
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::CloseParen);
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Dot);
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Dot);
+}
+
+#[test]
+fn character_test() {
+    let code = String::from(r"'a' '\n' '\0' '\x41' '\377' '\'' char");
+    let mut lexer = Lexer::new(code);
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Character(97));
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Character(10));
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Character(0));
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Character(65));
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Character(-1));
+    let quote = lexer.next().unwrap();
+    assert_eq!(quote.kind, TokenKind::Character(39));
+    assert_eq!(quote.span.len, 4);
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Char);
+}
+
+#[test]
+fn character_error_test() {
+    let code = String::from("'' 'ab' '\\q' 'a");
+    let mut lexer = Lexer::new(code);
+    let errors: Vec<TokenKind> = lexer.by_ref().map(|token| token.kind).collect();
+    assert_eq!(
+        errors,
+        vec![
+            TokenKind::Error("empty character literal".to_string()),
+            TokenKind::Error("character literal may only contain one character".to_string()),
+            TokenKind::Error("unknown escape sequence `\\q`".to_string()),
+            TokenKind::Error("unterminated character literal".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn string_test() {
+    let code = String::from("\"hi\\n\" \"\\\"\\x41\\101\\0\" \"\" \"é\" \"open");
+    let mut lexer = Lexer::new(code);
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Str(b"hi\n".to_vec()));
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        TokenKind::Str(b"\"AA\0".to_vec())
+    );
+    assert_eq!(lexer.next().unwrap().kind, TokenKind::Str(Vec::new()));
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        TokenKind::Str("é".as_bytes().to_vec())
+    );
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        TokenKind::Error("unterminated string literal".to_string())
+    );
+}
+
+#[test]
+fn span_test() {
+    let code = String::from("int a;\n  a = 42;");
+    let mut lexer = Lexer::with_file(code, 3);
+    let int = lexer.next().unwrap();
+    assert_eq!(int.span, Span::new(3, 0, 3, 1, 1));
+    lexer.next();
+    lexer.next();
+    let a = lexer.next().unwrap();
+    assert_eq!(a.kind, TokenKind::Ident("a".to_string()));
+    assert_eq!(a.span, Span::new(3, 9, 1, 2, 3));
+    lexer.next();
+    let num = lexer.next().unwrap();
+    assert_eq!(num.span, Span::new(3, 13, 2, 2, 7));
+}
+
+#[test]
+fn error_test() {
+    let code = String::from("a @ b ! c");
+    let mut lexer = Lexer::new(code);
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        TokenKind::Ident("a".to_string())
+    );
+    let unknown = lexer.next().unwrap();
+    assert_eq!(
+        unknown.kind,
+        TokenKind::Error("unknown character `@`".to_string())
+    );
+    assert_eq!(unknown.span, Span::new(0, 2, 1, 1, 3));
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        TokenKind::Ident("b".to_string())
+    );
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        TokenKind::Error("expected `=` after `!`".to_string())
+    );
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        TokenKind::Ident("c".to_string())
+    );
+    assert_eq!(lexer.next(), None);
+}
+
+#[test]
+fn underscore_test() {
+    let code = String::from("_foo_bar1");
+    let mut lexer = Lexer::new(code);
+    assert_eq!(
+        lexer.next().unwrap().kind,
+        TokenKind::Ident("_foo_bar1".to_string())
+    );
+}
@@ -1,8 +1,29 @@
+// The AST is built from boxed nodes with explicit early returns throughout;
+// keep clippy from fighting that style.
+#![allow(
+    clippy::upper_case_acronyms,
+    clippy::needless_return,
+    clippy::vec_box,
+    clippy::boxed_local
+)]
+// diagnostics are only built on the error path, their size doesn't matter
+#![allow(clippy::result_large_err)]
+
+mod diagnostic;
+mod driver;
 mod lexer;
 mod parser;
 mod rcc;
+mod sema;
+mod span;
+mod target;
+
+use std::env::args;
+use std::process::exit;
 
-fn main() -> Result<(), String> {
-    rcc::Rcc::run()?;
-    Ok(())
+fn main() {
+    if let Err(report) = driver::run(args().skip(1)) {
+        eprint!("{}", report);
+        exit(1);
+    }
 }
@@ -1,40 +1,438 @@
+use crate::diagnostic::{Diagnostic, E_SYNTAX, E_TOKEN, E_UNDEFINED};
 use crate::lexer::Lexer;
 use crate::lexer::Token;
 use crate::lexer::TokenKind;
+use crate::span::Span;
+use std::cell::OnceCell;
+use std::fmt;
+use std::rc::Rc;
 
 pub(crate) struct Parser {
     lexer: Lexer,
     curr: Option<Box<Token>>,
-    local: Vec<Vec<LVal>>, // local frames for functions
+    prev: Span,               // span of the last consumed token
+    local: Vec<Vec<LVal>>,    // local frames for functions
+    frame: Option<usize>,     // frame of the function being parsed, if any
+    scopes: Vec<Vec<usize>>,  // open blocks, innermost last; indices into the frame
+    names: Vec<Scope>,        // tags, enumerators and typedefs of file scope and each block
+    switches: Vec<Cases>,     // enclosing switch statements, innermost last
+    breakable: usize,         // enclosing loops and switches
+    globals: Vec<GVal>,       // file-scope variables
+    functions: Vec<Function>, // signatures of the functions defined so far
+    strings: Vec<Vec<u8>>,    // contents of the string literals, without the NUL
+    errors: Vec<Diagnostic>,  // errors recovered from so far
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) enum Type {
+    CHAR,  // signed, as on x86-64 System V
+    UCHAR, // unsigned char
+    SHORT,
+    USHORT,
     INT,
+    UINT,
+    LONG, // also `long long`, the same 8 bytes on x86-64
+    ULONG,
     PTR(Box<Type>),
+    ARRAY(Box<Type>, usize), // element type and length
+    STRUCT(Rc<Struct>),
+    // return type, parameter types, and `...`; `int ()` says nothing of its
+    // parameters and is kept as `...` alone, which no prototype can spell
+    FUNC(Box<Type>, Vec<Type>, bool),
+}
+
+impl Type {
+    // bytes a value of the type occupies, as laid out by System V x86-64
+    pub(crate) fn size(&self) -> usize {
+        match self {
+            Type::CHAR | Type::UCHAR => 1,
+            Type::SHORT | Type::USHORT => 2,
+            Type::INT | Type::UINT => 4,
+            Type::LONG | Type::ULONG => 8,
+            Type::PTR(_) => 8,
+            Type::ARRAY(elem, len) => elem.size() * len,
+            Type::STRUCT(def) => def.layout().map_or(0, |layout| layout.size),
+            // as GCC counts it, so a function pointer steps by bytes
+            Type::FUNC(..) => 1,
+        }
+    }
+
+    pub(crate) fn align(&self) -> usize {
+        match self {
+            Type::ARRAY(elem, _) => elem.align(),
+            Type::STRUCT(def) => def.layout().map_or(1, |layout| layout.align),
+            _ => self.size(),
+        }
+    }
+
+    // can an object of the type be declared? not until its size is known
+    pub(crate) fn is_complete(&self) -> bool {
+        match self {
+            Type::ARRAY(elem, len) => *len > 0 && elem.is_complete(),
+            Type::STRUCT(def) => def.layout().is_some(),
+            // a function is not an object at all
+            Type::FUNC(..) => false,
+            _ => true,
+        }
+    }
+
+    // the type pointed to, or the element type of an array
+    pub(crate) fn base(&self) -> Option<&Type> {
+        match self {
+            Type::PTR(base) | Type::ARRAY(base, _) => Some(base),
+            _ => None,
+        }
+    }
+
+    // a function type that leaves its parameters unspecified, as `int f()`
+    pub(crate) fn is_unprototyped(&self) -> bool {
+        matches!(self, Type::FUNC(_, params, true) if params.is_empty())
+    }
+
+    // can the two stand for each other? only if they are the same type, up
+    // to a function type with unspecified parameters meeting a prototype
+    pub(crate) fn is_compatible(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::PTR(base), Type::PTR(other)) => base.is_compatible(other),
+            (Type::FUNC(ret, ..), Type::FUNC(other_ret, ..))
+                if self.is_unprototyped() || other.is_unprototyped() =>
+            {
+                ret == other_ret
+            }
+            _ => self == other,
+        }
+    }
+
+    pub(crate) fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Type::CHAR
+                | Type::UCHAR
+                | Type::SHORT
+                | Type::USHORT
+                | Type::INT
+                | Type::UINT
+                | Type::LONG
+                | Type::ULONG
+        )
+    }
+
+    // pointers compare and divide as unsigned too
+    pub(crate) fn is_signed(&self) -> bool {
+        matches!(self, Type::CHAR | Type::SHORT | Type::INT | Type::LONG)
+    }
+
+    // the integer promotions: anything narrower than `int` computes as one
+    pub(crate) fn promote(&self) -> Type {
+        match self {
+            Type::CHAR | Type::UCHAR | Type::SHORT | Type::USHORT => Type::INT,
+            _ => self.clone(),
+        }
+    }
+
+    // the usual arithmetic conversions: the wider type wins, and unsigned
+    // wins between types of the same width
+    pub(crate) fn common(&self, other: &Type) -> Type {
+        let (lhs, rhs) = (self.promote(), other.promote());
+        if lhs.size() != rhs.size() {
+            return if lhs.size() > rhs.size() { lhs } else { rhs };
+        }
+        if lhs.is_signed() {
+            rhs
+        } else {
+            lhs
+        }
+    }
+}
+
+// written the way C spells it: `int *`, `int[3]`, `int (*)[3]`, `int (*)(int)`
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut ty = self;
+        let mut declarator = String::new();
+        let name = loop {
+            match ty {
+                Type::CHAR => break "char".to_string(),
+                Type::UCHAR => break "unsigned char".to_string(),
+                Type::SHORT => break "short".to_string(),
+                Type::USHORT => break "unsigned short".to_string(),
+                Type::INT => break "int".to_string(),
+                Type::UINT => break "unsigned int".to_string(),
+                Type::LONG => break "long".to_string(),
+                Type::ULONG => break "unsigned long".to_string(),
+                Type::STRUCT(def) => break def.to_string(),
+                Type::PTR(base) => {
+                    declarator.insert(0, '*');
+                    ty = base;
+                }
+                Type::ARRAY(elem, len) => {
+                    if declarator.starts_with('*') {
+                        declarator = format!("({})", declarator);
+                    }
+                    declarator.push_str(&format!("[{}]", len));
+                    ty = elem;
+                }
+                Type::FUNC(ret, params, variadic) => {
+                    if declarator.starts_with('*') {
+                        declarator = format!("({})", declarator);
+                    }
+                    let mut params: Vec<String> = params.iter().map(Type::to_string).collect();
+                    if params.is_empty() && !*variadic {
+                        params.push("void".to_string());
+                    } else if *variadic && !params.is_empty() {
+                        params.push("...".to_string());
+                    }
+                    declarator.push_str(&format!("({})", params.join(", ")));
+                    ty = ret;
+                }
+            }
+        };
+        if declarator.starts_with('*') || declarator.starts_with('(') {
+            write!(f, "{} {}", name, declarator)
+        } else {
+            write!(f, "{}{}", name, declarator)
+        }
+    }
+}
+
+// what a scope declares besides its variables
+#[derive(Default)]
+struct Scope {
+    tags: Vec<Tag>,
+    idents: Vec<(String, Ordinary, Span)>, // name, meaning, declaration
+}
+
+// an ordinary identifier that is not a variable
+#[derive(Clone)]
+enum Ordinary {
+    Enumerator(i64),
+    Typedef(Type),
+}
+
+// what a tag names; structs, unions and enums share one namespace
+#[derive(Clone)]
+enum Tag {
+    Struct(Rc<Struct>), // or union
+    Enum(String, Span),
+}
+
+impl Tag {
+    fn name(&self) -> Option<&str> {
+        match self {
+            Tag::Struct(def) => def.tag.as_deref(),
+            Tag::Enum(name, _) => Some(name),
+        }
+    }
+
+    fn span(&self) -> Span {
+        match self {
+            Tag::Struct(def) => def.span,
+            Tag::Enum(_, span) => *span,
+        }
+    }
+}
+
+// the case labels of a switch being parsed
+#[derive(Default)]
+struct Cases {
+    values: Vec<(i64, Span)>,
+    default: Option<Span>,
+}
+
+// a declarator as written, before it is applied to the base type: `*a[3]`
+// is an array of pointers, so the derivations are kept innermost first
+struct Declarator {
+    name: Option<(String, Span)>, // none in an abstract declarator
+    derives: Vec<Derive>,
+}
+
+// one step from a type to the type derived from it
+enum Derive {
+    Ptr,
+    Array(usize, Span),           // length, 0 if omitted, and the brackets
+    Func(Vec<Param>, bool, Span), // parameters, `...`, and the parentheses
+}
+
+// a parameter as declared; only a definition needs the names
+struct Param {
+    ty: Type,
+    name: Option<(String, Span)>,
+    span: Span, // the whole parameter declaration
+}
+
+// a struct type, shared by every use of its tag; the members are filled in
+// at the closing brace, so a struct can be named, and pointed to from its
+// own members, before it is complete
+pub(crate) struct Struct {
+    pub(crate) tag: Option<String>,
+    pub(crate) span: Span,  // where the tag was first seen
+    pub(crate) union: bool, // members all start at offset 0
+    layout: OnceCell<Layout>,
+}
+
+pub(crate) struct Layout {
+    pub(crate) members: Vec<Member>,
+    pub(crate) size: usize,
+    pub(crate) align: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Member {
+    pub(crate) name: String,
+    pub(crate) ty: Type,
+    pub(crate) offset: usize, // bytes from the start of the struct
+}
+
+impl Struct {
+    fn new(tag: Option<String>, span: Span, union: bool) -> Self {
+        Self {
+            tag,
+            span,
+            union,
+            layout: OnceCell::new(),
+        }
+    }
+
+    pub(crate) fn layout(&self) -> Option<&Layout> {
+        self.layout.get()
+    }
+
+    pub(crate) fn member(&self, name: &str) -> Option<&Member> {
+        self.layout()?
+            .members
+            .iter()
+            .find(|member| member.name == name)
+    }
+
+    // lay the members out in order, each at its natural alignment, and pad
+    // the whole to a multiple of the strictest one, as System V does; the
+    // members of a union overlap, so it is as large as the largest
+    fn complete(&self, members: Vec<(String, Type, Span)>) {
+        let mut size = 0;
+        let mut align = 1;
+        let members = members
+            .into_iter()
+            .map(|(name, ty, _)| {
+                let offset = if self.union {
+                    0
+                } else {
+                    align_to(size, ty.align())
+                };
+                size = size.max(offset + ty.size());
+                align = align.max(ty.align());
+                Member { name, ty, offset }
+            })
+            .collect();
+        let layout = Layout {
+            members,
+            size: align_to(size, align),
+            align,
+        };
+        let _ = self.layout.set(layout);
+    }
+}
+
+// two uses of a struct are the same type only if they name the same
+// definition
+impl PartialEq for Struct {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// not the members, which may point back to the struct itself
+impl fmt::Debug for Struct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Struct({})", self)
+    }
+}
+
+impl fmt::Display for Struct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keyword = if self.union { "union" } else { "struct" };
+        match &self.tag {
+            Some(tag) => write!(f, "{} {}", keyword, tag),
+            None => write!(f, "{} <anonymous>", keyword),
+        }
+    }
+}
+
+// the largest object, and the largest frame, the backend can address: a
+// frame offset, the `sub rsp` that makes room for the frame and the `.zero`
+// of a global all take a signed 32-bit number of bytes
+pub(crate) const MAX_OBJECT_SIZE: usize = i32::MAX as usize;
+
+// round `n` up to a multiple of `align`, a power of two
+pub(crate) fn align_to(n: usize, align: usize) -> usize {
+    (n + align - 1) & !(align - 1)
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) struct LVal {
     name: String,
     val_type: Type,
-    offset: u8,
+    offset: usize, // distance below rbp
+    span: Span,    // where it was declared
 }
 
 impl LVal {
-    fn new(name: String, val_type: Type, offset: u8) -> Self {
+    fn new(name: String, val_type: Type, offset: usize, span: Span) -> Self {
         Self {
             name,
             val_type,
             offset,
+            span,
         }
     }
 }
 
+// a file-scope variable
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct GVal {
+    pub(crate) name: String,
+    pub(crate) val_type: Type,
+    pub(crate) init: Option<Init>,
+    pub(crate) init_span: Span, // the initializer, if any
+    span: Span,                 // where it was first declared
+}
+
+// the signature of a function
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Function {
+    pub(crate) name: String,
+    pub(crate) ret: Type,
+    pub(crate) params: Vec<Type>,
+    pub(crate) variadic: bool, // takes more arguments after `...`
+    pub(crate) defined: bool,  // has a body in this file
+    pub(crate) span: Span,     // the name in the definition, or the first declaration
+}
+
+impl Function {
+    // the type its name designates
+    pub(crate) fn ty(&self) -> Type {
+        Type::FUNC(
+            Box::new(self.ret.clone()),
+            self.params.clone(),
+            self.variadic,
+        )
+    }
+}
+
+// the constant a global is initialized with
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Init {
+    Num(i64),
+    // `offset` bytes into another global or a function, as a pointer to `ty`
+    Addr { name: String, offset: i64, ty: Type },
+    Str(usize), // a string literal: its address, or its bytes for an array
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) enum NodeKind {
     NUM(i64),
-    LVAL(u8),
+    LVAL(usize),
+    GVAL(String),
+    Str(usize), // string literal, by index in the parser's table
     ADD,
     SUB,
     MUL,
@@ -60,13 +458,24 @@ pub(crate) enum NodeKind {
         name: String,
         argv: Vec<Box<Node>>,
     }, // function call
+    Call(Vec<Box<Node>>), // call through the function pointer lhs
     Def {
         name: String,
         args: usize,
         body: Box<Node>,
         local: usize, // index in the local frames
     }, // function definition
-    Declar, // define new variable
+    Declar,               // define new variable
+    Subscript,            // lhs[rhs]
+    Cast,                 // rhs converted to the node's type
+    Member(String),       // lhs.name; `p->name` is `(*p).name`
+    Switch {
+        cases: Vec<(i64, Span)>, // values and spans of the case labels, in order
+        default: bool,
+    }, // switch (lhs) rhs
+    Case(usize),          // the label of `cases[n]` of the enclosing switch, before lhs
+    Default,              // its default label, before lhs
+    Break,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -74,299 +483,1521 @@ pub(crate) struct Node {
     pub kind: NodeKind,
     pub lhs: Option<Box<Node>>,
     pub rhs: Option<Box<Node>>,
+    pub span: Span,
+    pub ty: Option<Type>, // set on variables by the parser, elsewhere by sema
 }
 
 impl Node {
     pub fn new(kind: NodeKind, lhs: Box<Node>, rhs: Box<Node>) -> Self {
+        let span = lhs.span.to(rhs.span);
         Self {
             kind,
             lhs: Some(lhs),
             rhs: Some(rhs),
+            span,
+            ty: None,
         }
     }
 
-    pub fn new_unary(kind: NodeKind, rhs: Box<Node>) -> Self {
+    // `op` is the span of the operator token
+    pub fn new_unary(kind: NodeKind, rhs: Box<Node>, op: Span) -> Self {
+        let span = op.to(rhs.span);
         Self {
             kind,
-            lhs: Some(Box::new(Node::new_leaf(NodeKind::NUM(0)))),
+            lhs: Some(Box::new(Node::new_leaf(NodeKind::NUM(0), op))),
             rhs: Some(rhs),
+            span,
+            ty: None,
         }
     }
 
-    pub fn new_leaf(kind: NodeKind) -> Self {
+    pub fn new_leaf(kind: NodeKind, span: Span) -> Self {
         Self {
             kind,
             lhs: None,
             rhs: None,
+            span,
+            ty: None,
+        }
+    }
+
+    // a variable reference, whose type is known as soon as it is parsed
+    pub fn new_var(kind: NodeKind, ty: Type, span: Span) -> Self {
+        Self {
+            ty: Some(ty),
+            ..Self::new_leaf(kind, span)
         }
     }
 }
 
 impl Parser {
-    pub fn load(src: String) -> Self {
+    pub fn with_file(src: String, file: usize) -> Self {
         Self {
-            lexer: Lexer::new(src),
+            lexer: Lexer::with_file(src, file),
             curr: None,
+            prev: Span::default(),
             local: Vec::new(),
+            frame: None,
+            scopes: Vec::new(),
+            names: vec![Scope::default()],
+            switches: Vec::new(),
+            breakable: 0,
+            globals: Vec::new(),
+            functions: Vec::new(),
+            strings: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
     fn consume(&mut self) {
+        if let Some(token) = &self.curr {
+            self.prev = token.span;
+        }
         self.curr = self.next_token();
     }
 
-    pub fn run(&mut self) -> Result<Vec<Box<Node>>, String> {
+    // span of the current token, or the end of input
+    fn span(&self) -> Span {
+        match &self.curr {
+            Some(token) => token.span,
+            None => self.prev.after(),
+        }
+    }
+
+    // report the current token as not fitting the grammar
+    fn unexpected(&self, message: &str) -> Diagnostic {
+        let label = match &self.curr {
+            Some(token) => format!("unexpected {}", token.kind),
+            None => "unexpected end of input".to_string(),
+        };
+        Diagnostic::error(E_SYNTAX, message, self.span()).with_label(&label)
+    }
+
+    // report a token missing right after the last consumed one
+    fn missing(&self, message: &str, token: &str) -> Diagnostic {
+        let span = self.prev.after();
+        Diagnostic::error(E_SYNTAX, message, span)
+            .with_label(&format!("expected `{}`", token))
+            .with_fixit(span, token)
+    }
+
+    // span from `start` up to the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.prev)
+    }
+
+    pub fn run(&mut self) -> Result<Vec<Box<Node>>, Vec<Diagnostic>> {
+        let (code, errors) = self.parse();
+        if errors.is_empty() {
+            Ok(code)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // parse the whole input, recovering from syntax errors; returns whatever
+    // could be parsed along with every error met on the way
+    pub fn parse(&mut self) -> (Vec<Box<Node>>, Vec<Diagnostic>) {
         self.consume();
-        self.parse_program()
+        let code = self.parse_program();
+        (code, std::mem::take(&mut self.errors))
     }
 
-    fn parse_program(&mut self) -> Result<Vec<Box<Node>>, String> {
+    fn parse_program(&mut self) -> Vec<Box<Node>> {
         let mut code = Vec::new();
         loop {
-            if let None = self.curr {
-                return Ok(code);
+            if self.curr.is_none() {
+                return code;
             }
-            if self.consume_token(TokenKind::Int) {
-                let function = self.parse_def()?;
-                code.push(function);
+            let start = self.span();
+            let decl = if self.consume_token(TokenKind::Typedef) {
+                self.parse_typedef().map(|_| None)
+            } else if self.at_type() {
+                let tagged = self.at_tag();
+                self.parse_base_type().and_then(|base| {
+                    // `struct s { ... };` only declares the tag
+                    if tagged && self.consume_token(TokenKind::SemiCol) {
+                        return Ok(None);
+                    }
+                    self.parse_def(base, start)
+                })
             } else {
-                return Err(String::from("expected function."));
+                Err(self
+                    .unexpected("expected declaration.")
+                    .with_note("only functions and variables may appear at file scope"))
+            };
+            match decl {
+                Ok(Some(function)) => code.push(function),
+                Ok(None) => {}
+                Err(err) => {
+                    self.report(err);
+                    self.sync_program(start);
+                }
+            }
+        }
+    }
+
+    // skip to the next type outside of any braces
+    fn sync_program(&mut self, start: Span) {
+        let mut depth = 0usize;
+        if self.span() == start {
+            self.consume();
+        }
+        loop {
+            match &self.curr {
+                None => return,
+                Some(token) => match token.kind {
+                    ref kind if depth == 0 && is_type_keyword(kind) => return,
+                    TokenKind::OpenCur => depth += 1,
+                    TokenKind::CloseCur => depth = depth.saturating_sub(1),
+                    _ => {}
+                },
+            }
+            self.consume();
+        }
+    }
+
+    // skip past the next `;`, or up to the `}` closing the current block or
+    // a type starting the next declaration
+    fn sync_stmt(&mut self, start: Span) {
+        let mut depth = 0usize;
+        if self.span() == start && !self.peek_token(TokenKind::CloseCur) {
+            self.consume();
+        }
+        loop {
+            match &self.curr {
+                None => return,
+                Some(token) => match token.kind {
+                    TokenKind::SemiCol if depth == 0 => {
+                        self.consume();
+                        return;
+                    }
+                    TokenKind::CloseCur if depth == 0 => return,
+                    ref kind if depth == 0 && is_type_keyword(kind) => return,
+                    TokenKind::OpenCur => depth += 1,
+                    TokenKind::CloseCur => depth -= 1,
+                    _ => {}
+                },
+            }
+            self.consume();
+        }
+    }
+
+    // a function definition, or global variables which leave no node behind
+    fn parse_def(&mut self, base: Type, start: Span) -> Result<Option<Box<Node>>, Diagnostic> {
+        let mut decl = self.parse_declarator(Some("expected function or variable name"))?;
+        let (name, span) = decl.name.clone().unwrap();
+        let val_type = Self::derive(base.clone(), &decl.derives)?;
+        // the parameters are named by the function's own declarator, the
+        // one applied last
+        match (val_type, decl.derives.pop()) {
+            (Type::FUNC(ret, types, variadic), Some(Derive::Func(params, ..)))
+                if self.peek_token(TokenKind::OpenCur) =>
+            {
+                // the `()` of a definition declares no parameters at all
+                let ty = Type::FUNC(ret, types, variadic && !params.is_empty());
+                Ok(Some(self.parse_func(ty, params, name, span, start)?))
+            }
+            (val_type, _) => {
+                self.parse_global(base, val_type, name, span)?;
+                Ok(None)
+            }
+        }
+    }
+
+    // `*p`, `a[3]` or `(*fp)(int)`: the pointers bind looser than the
+    // suffixes, and parentheses regroup them; `expected` is the error for a
+    // missing name, and without it the name may be left out, as in a cast
+    fn parse_declarator(&mut self, expected: Option<&str>) -> Result<Declarator, Diagnostic> {
+        let mut ptrs = 0;
+        while self.consume_token(TokenKind::Star) {
+            ptrs += 1;
+        }
+        let start = self.span();
+        let mut name = None;
+        let mut inner = None;
+        let mut suffixes = Vec::new();
+        if self.consume_token(TokenKind::OpenParen) {
+            // `int (int)` is an abstract declarator of a function
+            if expected.is_none()
+                && (self.at_type()
+                    || self.peek_token(TokenKind::Void)
+                    || self.peek_token(TokenKind::CloseParen))
+            {
+                let (params, variadic) = self.parse_params(start)?;
+                suffixes.push(Derive::Func(params, variadic, self.span_from(start)));
+            } else {
+                inner = Some(self.parse_declarator(expected)?);
+                if !self.consume_token(TokenKind::CloseParen) {
+                    return Err(self
+                        .missing("expected close parenthesis", ")")
+                        .with_secondary(start, "to match this `(`"));
+                }
+            }
+        } else if let Some(TokenKind::Ident(_)) = self.curr.as_ref().map(|token| &token.kind) {
+            name = Some(self.parse_name("expected name")?);
+        } else if let Some(message) = expected {
+            return Err(self.unexpected(message));
+        }
+        loop {
+            let open = self.span();
+            if self.consume_token(TokenKind::OpenSquare) {
+                let len = self.parse_len(open)?;
+                suffixes.push(Derive::Array(len, self.span_from(open)));
+            } else if self.consume_token(TokenKind::OpenParen) {
+                let (params, variadic) = self.parse_params(open)?;
+                suffixes.push(Derive::Func(params, variadic, self.span_from(open)));
+            } else {
+                break;
+            }
+        }
+        // the pointers apply to the base type first, then the suffixes from
+        // the right, `a[2][3]` being two `int[3]`, then the parenthesized part
+        let mut derives: Vec<Derive> = (0..ptrs).map(|_| Derive::Ptr).collect();
+        derives.extend(suffixes.into_iter().rev());
+        if let Some(inner) = inner {
+            derives.extend(inner.derives);
+            name = inner.name;
+        }
+        Ok(Declarator { name, derives })
+    }
+
+    // a declarator that has to name something, with the type it declares
+    fn parse_named(
+        &mut self,
+        base: &Type,
+        expected: &str,
+    ) -> Result<(Type, String, Span), Diagnostic> {
+        let decl = self.parse_declarator(Some(expected))?;
+        let (name, span) = decl.name.unwrap();
+        Ok((Self::derive(base.clone(), &decl.derives)?, name, span))
+    }
+
+    // `int *` or `int (*)[3]`, as in a cast
+    fn parse_type_name(&mut self) -> Result<Type, Diagnostic> {
+        let base = self.parse_base_type()?;
+        let decl = self.parse_declarator(None)?;
+        if let Some((name, span)) = decl.name {
+            return Err(Diagnostic::error(
+                E_SYNTAX,
+                &format!("unexpected identifier `{}` in type name", name),
+                span,
+            ));
+        }
+        Self::derive(base, &decl.derives)
+    }
+
+    // the type a declarator gives to its base type
+    fn derive(base: Type, derives: &[Derive]) -> Result<Type, Diagnostic> {
+        let mut ty = base;
+        for derive in derives {
+            ty = match derive {
+                Derive::Ptr => Type::PTR(Box::new(ty)),
+                Derive::Array(len, span) => {
+                    match ty {
+                        Type::ARRAY(_, 0) => {
+                            return Err(Diagnostic::error(E_SYNTAX, "array size missing", *span)
+                                .with_note("only the first dimension of an array may be omitted"))
+                        }
+                        Type::FUNC(..) => {
+                            return Err(Diagnostic::error(
+                                E_SYNTAX,
+                                &format!("array of functions of type `{}`", ty),
+                                *span,
+                            )
+                            .with_note("use an array of function pointers instead"))
+                        }
+                        _ => {}
+                    }
+                    let size = ty.size().checked_mul(*len);
+                    if size.is_none_or(|size| size > MAX_OBJECT_SIZE) {
+                        return Err(Diagnostic::error(
+                            E_SYNTAX,
+                            &format!("array of {} `{}` is too large", len, ty),
+                            *span,
+                        )
+                        .with_note(&format!(
+                            "an object may be at most {} bytes",
+                            MAX_OBJECT_SIZE
+                        )));
+                    }
+                    Type::ARRAY(Box::new(ty), *len)
+                }
+                Derive::Func(params, variadic, span) => {
+                    let message = match ty {
+                        Type::ARRAY(..) => "functions cannot return arrays",
+                        Type::FUNC(..) => "functions cannot return functions",
+                        Type::STRUCT(_) => "returning a struct by value is not supported",
+                        _ => "",
+                    };
+                    if !message.is_empty() {
+                        return Err(Diagnostic::error(E_SYNTAX, message, *span)
+                            .with_note("return a pointer instead"));
+                    }
+                    let params = params.iter().map(|param| param.ty.clone()).collect();
+                    Type::FUNC(Box::new(ty), params, *variadic)
+                }
+            };
+        }
+        Ok(ty)
+    }
+
+    // `int a, char *, ...)` after the open parenthesis, and whether it ends
+    // in `...`; an array or function parameter is a pointer to its first
+    // element or to the function. `)` alone leaves the parameters
+    // unspecified, and reads as `...`, while `void)` is an empty list
+    fn parse_params(&mut self, open: Span) -> Result<(Vec<Param>, bool), Diagnostic> {
+        let mut params = Vec::new();
+        if self.consume_token(TokenKind::CloseParen) {
+            return Ok((params, true));
+        }
+        if self.consume_token(TokenKind::Void) {
+            if !self.consume_token(TokenKind::CloseParen) {
+                return Err(self
+                    .missing("expected close parenthesis", ")")
+                    .with_note("`void` only stands for an empty parameter list"));
+            }
+            return Ok((params, false));
+        }
+        loop {
+            if self.peek_token(TokenKind::Ellipsis) {
+                if params.is_empty() {
+                    return Err(Diagnostic::error(
+                        E_SYNTAX,
+                        "`...` needs a named parameter before it",
+                        self.span(),
+                    ));
+                }
+                self.consume();
+                if !self.consume_token(TokenKind::CloseParen) {
+                    return Err(self
+                        .missing("expected close parenthesis", ")")
+                        .with_note("`...` comes after the last parameter"));
+                }
+                return Ok((params, true));
+            }
+            if !self.at_type() {
+                return Err(self.unexpected("expected parameter type"));
+            }
+            let start = self.span();
+            let base = self.parse_base_type()?;
+            let decl = self.parse_declarator(None)?;
+            let ty = match Self::derive(base, &decl.derives)? {
+                Type::ARRAY(elem, _) => Type::PTR(elem),
+                ty @ Type::FUNC(..) => Type::PTR(Box::new(ty)),
+                ty => ty,
+            };
+            let span = self.span_from(start);
+            if let Type::STRUCT(_) = ty {
+                return Err(Diagnostic::error(
+                    E_SYNTAX,
+                    "passing a struct by value is not supported",
+                    span,
+                )
+                .with_note("pass a pointer to the struct instead"));
+            }
+            params.push(Param {
+                ty,
+                name: decl.name,
+                span,
+            });
+            if self.consume_token(TokenKind::CloseParen) {
+                return Ok((params, false));
+            }
+            if !self.consume_token(TokenKind::Comma) {
+                return Err(self
+                    .missing("expected close parenthesis", ")")
+                    .with_secondary(open, "to match this `(`"));
             }
         }
     }
 
-    fn parse_def(&mut self) -> Result<Box<Node>, String> {
+    // does the current token start a declaration?
+    // a typedef name counts, unless a variable hides it
+    fn at_type(&self) -> bool {
         match &self.curr {
-            None => return Err("no new token".to_string()),
-            Some(token) => match token.kind.to_owned() {
-                TokenKind::Ident(name) => {
-                    self.consume();
-                    self.parse_func(name)
+            Some(token) => is_type_keyword(&token.kind) || self.find_typedef().is_some(),
+            None => false,
+        }
+    }
+
+    // does the current token start a struct, union or enum specifier?
+    fn at_tag(&self) -> bool {
+        matches!(
+            self.curr.as_ref().map(|token| &token.kind),
+            Some(TokenKind::Struct | TokenKind::Union | TokenKind::Enum)
+        )
+    }
+
+    // the integer type named by a run of specifiers in any order, such as
+    // `unsigned char`, `short int` or `long unsigned long`
+    fn parse_base_type(&mut self) -> Result<Type, Diagnostic> {
+        let start = self.span();
+        if self.peek_token(TokenKind::Struct) || self.peek_token(TokenKind::Union) {
+            return self.parse_struct();
+        }
+        if self.peek_token(TokenKind::Enum) {
+            return self.parse_enum();
+        }
+        if let Some(ty) = self.find_typedef() {
+            self.consume();
+            return Ok(ty);
+        }
+        let (mut signed, mut unsigned, mut char, mut short, mut int, mut long) = (0, 0, 0, 0, 0, 0);
+        while let Some(kind) = self.curr.as_ref().map(|token| token.kind.clone()) {
+            match kind {
+                TokenKind::Signed => signed += 1,
+                TokenKind::Unsigned => unsigned += 1,
+                TokenKind::Char => char += 1,
+                TokenKind::Short => short += 1,
+                TokenKind::Int => int += 1,
+                TokenKind::Long => long += 1,
+                _ => break,
+            }
+            self.consume();
+        }
+        if signed + unsigned + char + short + int + long == 0 {
+            return Err(self.unexpected("expected type"));
+        }
+        let valid = signed + unsigned <= 1
+            && int <= 1
+            && match (char, short, long) {
+                (0, 0, _) => long <= 2,
+                (1, 0, 0) => int == 0,
+                (0, 1, 0) => true,
+                _ => false,
+            };
+        if !valid {
+            return Err(Diagnostic::error(
+                E_SYNTAX,
+                "invalid combination of type specifiers",
+                self.span_from(start),
+            ));
+        }
+        let unsigned = unsigned == 1;
+        Ok(match (char, short, long, unsigned) {
+            (1, _, _, false) => Type::CHAR,
+            (1, _, _, true) => Type::UCHAR,
+            (_, 1, _, false) => Type::SHORT,
+            (_, 1, _, true) => Type::USHORT,
+            (_, _, 0, false) => Type::INT,
+            (_, _, 0, true) => Type::UINT,
+            (_, _, _, false) => Type::LONG,
+            (_, _, _, true) => Type::ULONG,
+        })
+    }
+
+    // `struct tag`, `struct tag { members }` or `struct { members }`, and
+    // the same with `union`
+    fn parse_struct(&mut self) -> Result<Type, Diagnostic> {
+        let union = self.peek_token(TokenKind::Union);
+        self.consume();
+        let tag = self.parse_tag()?;
+        if !self.peek_token(TokenKind::OpenCur) {
+            let (tag, span) = match tag {
+                Some(tag) => tag,
+                None => return Err(self.unexpected("expected tag or `{`")),
+            };
+            // a tag used before its definition declares it
+            return match self.find_tag(&tag) {
+                Some(Tag::Struct(def)) if def.union == union => Ok(Type::STRUCT(def)),
+                Some(prev) => Err(Self::tag_mismatch(&tag, span, &prev)),
+                None => {
+                    let def = Rc::new(Struct::new(Some(tag), span, union));
+                    let scope = self.names.last_mut().unwrap();
+                    scope.tags.push(Tag::Struct(def.clone()));
+                    Ok(Type::STRUCT(def))
                 }
-                _ => return Err("expected function name".to_string()),
-            },
+            };
         }
+        // a definition completes a declaration of the same scope, or
+        // starts a new type shadowing any outer one
+        let start = self.span();
+        let prev = tag.as_ref().and_then(|(tag, _)| {
+            let scope = self.names.last().unwrap();
+            scope
+                .tags
+                .iter()
+                .find(|prev| prev.name() == Some(tag))
+                .cloned()
+        });
+        let def = match (prev, &tag) {
+            (Some(Tag::Struct(prev)), Some((_, span)))
+                if prev.union == union && prev.layout().is_some() =>
+            {
+                return Err(Diagnostic::error(
+                    E_SYNTAX,
+                    &format!("redefinition of `{}`", prev),
+                    *span,
+                )
+                .with_secondary(prev.span, "first defined here"))
+            }
+            (Some(Tag::Struct(prev)), _) if prev.union == union => prev,
+            (Some(prev), Some((tag, span))) => return Err(Self::tag_mismatch(tag, *span, &prev)),
+            _ => {
+                let span = tag.as_ref().map_or(start, |(_, span)| *span);
+                let def = Rc::new(Struct::new(tag.map(|(tag, _)| tag), span, union));
+                if def.tag.is_some() {
+                    let scope = self.names.last_mut().unwrap();
+                    scope.tags.push(Tag::Struct(def.clone()));
+                }
+                def
+            }
+        };
+        self.consume();
+        let members = self.parse_members(start)?;
+        def.complete(members);
+        if def.layout().unwrap().size > MAX_OBJECT_SIZE {
+            return Err(
+                Diagnostic::error(E_SYNTAX, &format!("`{}` is too large", def), def.span)
+                    .with_note(&format!(
+                        "an object may be at most {} bytes",
+                        MAX_OBJECT_SIZE
+                    )),
+            );
+        }
+        Ok(Type::STRUCT(def))
     }
 
-    fn parse_func(&mut self, name: String) -> Result<Box<Node>, String> {
-        let local = self.local.len();
-        self.local.push(Vec::new());
-        let args = self.parse_args()?;
+    // the tag after `struct`, `union` or `enum`, if any
+    fn parse_tag(&mut self) -> Result<Option<(String, Span)>, Diagnostic> {
+        match self.curr.as_ref().map(|token| &token.kind) {
+            Some(TokenKind::Ident(_)) => Ok(Some(self.parse_name("expected tag")?)),
+            _ => Ok(None),
+        }
+    }
+
+    fn tag_mismatch(tag: &str, span: Span, prev: &Tag) -> Diagnostic {
+        Diagnostic::error(
+            E_SYNTAX,
+            &format!(
+                "use of `{}` with a tag type that does not match its declaration",
+                tag
+            ),
+            span,
+        )
+        .with_secondary(prev.span(), "previously declared here")
+    }
+
+    // `enum tag`, or `enum tag { A, B = 5, C }` whose enumerators are `int`
+    // constants; the type itself is `int`
+    fn parse_enum(&mut self) -> Result<Type, Diagnostic> {
+        self.consume();
+        let tag = self.parse_tag()?;
         if !self.peek_token(TokenKind::OpenCur) {
-            return Err("expected function body.".to_string());
+            let (tag, span) = match tag {
+                Some(tag) => tag,
+                None => return Err(self.unexpected("expected tag or `{`")),
+            };
+            return match self.find_tag(&tag) {
+                Some(Tag::Enum(..)) => Ok(Type::INT),
+                Some(prev) => Err(Self::tag_mismatch(&tag, span, &prev)),
+                None => Err(Diagnostic::error(
+                    E_UNDEFINED,
+                    &format!("`enum {}` is not defined", tag),
+                    span,
+                )
+                .with_note("an enum must be defined before it is used")),
+            };
         }
-        let body = self.parse_stmt()?;
-        Ok(Box::new(Node::new_leaf(NodeKind::Def {
-            name,
-            args,
-            body,
-            local,
-        })))
+        if let Some((tag, span)) = tag {
+            let scope = self.names.last().unwrap();
+            if let Some(prev) = scope.tags.iter().find(|prev| prev.name() == Some(&tag)) {
+                return Err(match prev {
+                    Tag::Enum(..) => Diagnostic::error(
+                        E_SYNTAX,
+                        &format!("redefinition of `enum {}`", tag),
+                        span,
+                    )
+                    .with_secondary(prev.span(), "first defined here"),
+                    _ => Self::tag_mismatch(&tag, span, prev),
+                });
+            }
+            let scope = self.names.last_mut().unwrap();
+            scope.tags.push(Tag::Enum(tag, span));
+        }
+        let open = self.span();
+        self.consume();
+        let mut value: i64 = 0;
+        loop {
+            if self.consume_token(TokenKind::CloseCur) {
+                return Ok(Type::INT);
+            }
+            let (name, span) = self.parse_name("expected enumerator name")?;
+            if self.consume_token(TokenKind::Eq) {
+                let expr = self.parse_assign()?;
+                value = match Self::eval_const(&expr) {
+                    Some(value) => value,
+                    None => {
+                        return Err(Diagnostic::error(
+                            E_SYNTAX,
+                            "enumerator value is not a compile-time constant",
+                            expr.span,
+                        ))
+                    }
+                };
+            }
+            if i32::try_from(value).is_err() {
+                return Err(Diagnostic::error(
+                    E_SYNTAX,
+                    &format!("enumerator value {} does not fit in `int`", value),
+                    span,
+                ));
+            }
+            self.declare(name, Ordinary::Enumerator(value), span)?;
+            value += 1;
+            if self.consume_token(TokenKind::CloseCur) {
+                return Ok(Type::INT);
+            }
+            if !self.consume_token(TokenKind::Comma) {
+                return Err(self
+                    .missing("expected close brace", "}")
+                    .with_secondary(open, "to match this `{`"));
+            }
+        }
+    }
+
+    // enumerators and typedef names share their scope's namespace with its
+    // variables
+    fn declare(&mut self, name: String, ident: Ordinary, span: Span) -> Result<(), Diagnostic> {
+        let prev = match self.scopes.len() {
+            0 => self
+                .globals
+                .iter()
+                .find(|gval| gval.name == name)
+                .map(|gval| gval.span),
+            _ => {
+                let frame = &self.local[self.frame.unwrap()];
+                self.scopes
+                    .last()
+                    .unwrap()
+                    .iter()
+                    .find(|&&index| frame[index].name == name)
+                    .map(|&index| frame[index].span)
+            }
+        };
+        let scope = self.names.last().unwrap();
+        if let Some((_, prev, prev_span)) = scope.idents.iter().find(|(prev, ..)| *prev == name) {
+            match (prev, &ident) {
+                // a typedef may be repeated as long as it names the same type
+                (Ordinary::Typedef(prev), Ordinary::Typedef(ty)) if prev == ty => return Ok(()),
+                (Ordinary::Typedef(prev), Ordinary::Typedef(ty)) => {
+                    return Err(Diagnostic::error(
+                        E_SYNTAX,
+                        &format!(
+                            "typedef redefinition with different types (`{}` vs `{}`)",
+                            ty, prev
+                        ),
+                        span,
+                    )
+                    .with_secondary(*prev_span, "first declared here"))
+                }
+                _ => {}
+            }
+        }
+        if let Some(prev) = prev.or_else(|| self.ident_conflict(&name)) {
+            return Err(
+                Diagnostic::error(E_SYNTAX, &format!("redefinition of `{}`", name), span)
+                    .with_secondary(prev, "first declared here"),
+            );
+        }
+        let scope = self.names.last_mut().unwrap();
+        scope.idents.push((name, ident, span));
+        Ok(())
     }
 
-    fn parse_arg(&mut self, val_type: Type) -> Result<(), String> {
+    // `int a, *b; char c[4];` up to the closing brace of a struct
+    fn parse_members(&mut self, open: Span) -> Result<Vec<(String, Type, Span)>, Diagnostic> {
+        let mut members: Vec<(String, Type, Span)> = Vec::new();
+        loop {
+            if self.consume_token(TokenKind::CloseCur) {
+                return Ok(members);
+            }
+            if self.curr.is_none() {
+                return Err(self
+                    .missing("expected close brace", "}")
+                    .with_secondary(open, "to match this `{`"));
+            }
+            let base = self.parse_base_type()?;
+            loop {
+                let (ty, name, span) = self.parse_named(&base, "expected member name")?;
+                if let Type::FUNC(..) = ty {
+                    return Err(Diagnostic::error(
+                        E_SYNTAX,
+                        &format!("member `{}` has function type `{}`", name, ty),
+                        span,
+                    )
+                    .with_note("use a function pointer instead"));
+                }
+                if !ty.is_complete() {
+                    return Err(Diagnostic::error(
+                        E_SYNTAX,
+                        &format!("member `{}` has incomplete type `{}`", name, ty),
+                        span,
+                    ));
+                }
+                if let Some((_, _, prev)) = members.iter().find(|member| member.0 == name) {
+                    return Err(Diagnostic::error(
+                        E_SYNTAX,
+                        &format!("duplicate member `{}`", name),
+                        span,
+                    )
+                    .with_secondary(*prev, "first declared here"));
+                }
+                members.push((name, ty, span));
+                if self.consume_token(TokenKind::SemiCol) {
+                    break;
+                }
+                if !self.consume_token(TokenKind::Comma) {
+                    return Err(self.missing("expected semicolon", ";"));
+                }
+            }
+        }
+    }
+
+    // variables need a size; pointers to incomplete structs do not
+    fn check_complete(val_type: &Type, name: &str, span: Span) -> Result<(), Diagnostic> {
+        if val_type.is_complete() {
+            return Ok(());
+        }
+        Err(Diagnostic::error(
+            E_SYNTAX,
+            &format!("variable `{}` has incomplete type `{}`", name, val_type),
+            span,
+        ))
+    }
+
+    // the identifier at the cursor
+    fn parse_name(&mut self, message: &str) -> Result<(String, Span), Diagnostic> {
         match &self.curr {
-            None => return Err("expected argument".to_string()),
             Some(token) => match token.kind.to_owned() {
-                TokenKind::Ident(arg) => {
+                TokenKind::Ident(name) => {
+                    let span = token.span;
                     self.consume();
-                    self.push_local(val_type, arg.clone());
-                    Ok(())
+                    Ok((name, span))
                 }
-                _ => return Err("expected argument".to_string()),
+                _ => Err(self.unexpected(message)),
             },
+            None => Err(self.unexpected(message)),
         }
     }
 
-    fn parse_ptr(&mut self, init_type: Type) -> Type {
-        let mut val_type = init_type;
+    // `int a = 1, *b = &a;` at file scope, after the first name; a
+    // declarator of a function declares it instead
+    fn parse_global(
+        &mut self,
+        base: Type,
+        val_type: Type,
+        name: String,
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        let (mut val_type, mut name, mut span) = (val_type, name, span);
         loop {
-            if self.consume_token(TokenKind::Star) {
-                val_type = Type::PTR(Box::new(val_type));
+            if let Type::FUNC(..) = val_type {
+                self.declare_function(name, val_type, span, false)?;
             } else {
-                return val_type;
+                self.parse_gval(val_type, name, span)?;
+            }
+            if self.consume_token(TokenKind::SemiCol) {
+                return Ok(());
             }
+            if !self.consume_token(TokenKind::Comma) {
+                return Err(self.missing("expected semicolon", ";"));
+            }
+            (val_type, name, span) = self.parse_named(&base, "expected variable name")?;
         }
     }
 
-    fn parse_args(&mut self) -> Result<usize, String> {
-        if !self.consume_token(TokenKind::OpenParen) {
-            return Err("expected open parenthesis.".to_string());
+    // the initializer of a global and its declaration
+    fn parse_gval(&mut self, val_type: Type, name: String, span: Span) -> Result<(), Diagnostic> {
+        let expr = self.parse_init(&val_type, span)?;
+        let val_type = self.complete_array(val_type, expr.as_deref(), span)?;
+        Self::check_complete(&val_type, &name, span)?;
+        let init_span = expr.as_ref().map_or(span, |expr| expr.span);
+        let init = if let Some(expr) = expr {
+            match Self::eval_init(&expr) {
+                Some(init) => Some(init),
+                None => {
+                    return Err(Diagnostic::error(
+                        E_SYNTAX,
+                        "initializer element is not a compile-time constant",
+                        expr.span,
+                    )
+                    .with_note("globals are initialized with a number or the address of a global"))
+                }
+            }
+        } else {
+            None
+        };
+        self.push_global(GVal {
+            name,
+            val_type,
+            init,
+            init_span,
+            span,
+        })
+    }
+
+    // the length between `[` and `]`; `[]` leaves it 0 for the initializer
+    // to fill in
+    fn parse_len(&mut self, open: Span) -> Result<usize, Diagnostic> {
+        if self.consume_token(TokenKind::CloseSquare) {
+            return Ok(0);
         }
-        let mut args = 0;
-        if self.consume_token(TokenKind::CloseParen) {
-            return Ok(args);
+        let expr = self.parse_expr()?;
+        let len = match Self::eval_const(&expr) {
+            Some(len) if len > MAX_OBJECT_SIZE as i64 => {
+                return Err(
+                    Diagnostic::error(E_SYNTAX, "array size is too large", expr.span).with_note(
+                        &format!("an object may be at most {} bytes", MAX_OBJECT_SIZE),
+                    ),
+                )
+            }
+            Some(len) if len > 0 => len as usize,
+            Some(_) => {
+                return Err(Diagnostic::error(
+                    E_SYNTAX,
+                    "array size must be positive",
+                    expr.span,
+                ))
+            }
+            None => {
+                return Err(Diagnostic::error(
+                    E_SYNTAX,
+                    "array size is not a compile-time constant",
+                    expr.span,
+                ))
+            }
+        };
+        if !self.consume_token(TokenKind::CloseSquare) {
+            return Err(self
+                .missing("expected close bracket", "]")
+                .with_secondary(open, "to match this `[`"));
         }
-        loop {
-            match &self.curr {
-                None => return Err("no new token".to_string()),
-                Some(token) => match token.kind.to_owned() {
-                    TokenKind::Int => {
-                        self.consume();
-                        let val_type = self.parse_ptr(Type::INT);
-                        args += 1;
-                        self.parse_arg(val_type)?;
-                        if self.consume_token(TokenKind::CloseParen) {
-                            return Ok(args);
-                        }
-                        if self.consume_token(TokenKind::Comma) {
-                            continue;
-                        }
-                    }
-                    _ => return Err("unexpected token".to_string()),
-                },
+        Ok(len)
+    }
+
+    // `= expr` after a declarator; arrays can only be initialized by strings
+    fn parse_init(&mut self, val_type: &Type, name: Span) -> Result<Option<Box<Node>>, Diagnostic> {
+        if !self.consume_token(TokenKind::Eq) {
+            return Ok(None);
+        }
+        let init = self.parse_assign()?;
+        match (val_type, &init.kind) {
+            (Type::ARRAY(..), NodeKind::Str(_)) => {}
+            (Type::ARRAY(..), _) => {
+                return Err(Diagnostic::error(
+                    E_SYNTAX,
+                    "array initializers are not supported",
+                    init.span,
+                )
+                .with_secondary(name, "this is an array")
+                .with_note("only a string literal can initialize an array"))
             }
+            _ => {}
         }
+        Ok(Some(init))
     }
 
-    fn parse_var(&mut self, val_type: Type) -> Result<Box<Node>, String> {
-        match &self.curr {
-            None => return Err("expected variable name".to_string()),
-            Some(token) => match token.kind.to_owned() {
-                TokenKind::Ident(name) => {
-                    self.consume();
-                    let offset = self.push_local(val_type, name.clone());
-                    if self.consume_token(TokenKind::SemiCol) {
-                        return Ok(Box::new(Node {
-                            kind: NodeKind::Declar,
-                            lhs: Some(Box::new(Node::new_leaf(NodeKind::LVAL(offset)))),
-                            rhs: None,
-                        }));
-                    } else {
-                        return Err("TODO".to_string());
+    // the length of `char s[] = "..."` comes from its initializer
+    fn complete_array(
+        &self,
+        val_type: Type,
+        init: Option<&Node>,
+        name: Span,
+    ) -> Result<Type, Diagnostic> {
+        match (val_type, init.and_then(|init| init.ty.as_ref())) {
+            (Type::ARRAY(elem, 0), Some(Type::ARRAY(_, len))) => Ok(Type::ARRAY(elem, *len)),
+            (Type::ARRAY(_, 0), _) => Err(Diagnostic::error(E_SYNTAX, "array size missing", name)
+                .with_note("only parameters and arrays initialized by a string may omit the size")),
+            (val_type, _) => Ok(val_type),
+        }
+    }
+
+    // fold a global's initializer into a constant
+    fn eval_init(node: &Node) -> Option<Init> {
+        let addr = |name: &String, offset, ty: &Type| Init::Addr {
+            name: name.clone(),
+            offset,
+            ty: ty.clone(),
+        };
+        match (&node.kind, &node.ty) {
+            (NodeKind::Str(id), _) => Some(Init::Str(*id)),
+            (NodeKind::Addr, _) => {
+                let lval = node.rhs.as_ref()?;
+                match (&lval.kind, &lval.ty) {
+                    (NodeKind::GVAL(name), Some(ty)) => Some(addr(name, 0, ty)),
+                    // `&a[2]`, a constant distance into a global array
+                    (NodeKind::Subscript, _) => {
+                        let array = lval.lhs.as_ref()?;
+                        let (NodeKind::GVAL(name), Some(Type::ARRAY(elem, _))) =
+                            (&array.kind, &array.ty)
+                        else {
+                            return None;
+                        };
+                        let index = Self::eval_const(lval.rhs.as_ref()?)?;
+                        let offset = index.checked_mul(elem.size() as i64)?;
+                        Some(addr(name, offset, elem))
                     }
+                    _ => None,
                 }
-                _ => return Err("expected variable name".to_string()),
+            }
+            // a function is its address, and an array that of its first element
+            (NodeKind::GVAL(name), Some(ty @ Type::FUNC(..))) => Some(addr(name, 0, ty)),
+            (NodeKind::GVAL(name), Some(Type::ARRAY(elem, _))) => Some(addr(name, 0, elem)),
+            _ => Some(Init::Num(Self::eval_const(node)?)),
+        }
+    }
+
+    fn eval_const(node: &Node) -> Option<i64> {
+        if let NodeKind::NUM(num) = node.kind {
+            return Some(num);
+        }
+        let lhs = Self::eval_const(node.lhs.as_ref()?)?;
+        let rhs = Self::eval_const(node.rhs.as_ref()?)?;
+        match node.kind {
+            NodeKind::ADD => lhs.checked_add(rhs),
+            NodeKind::SUB => lhs.checked_sub(rhs),
+            NodeKind::MUL => lhs.checked_mul(rhs),
+            NodeKind::DIV => lhs.checked_div(rhs),
+            _ => None,
+        }
+    }
+
+    // a global may be declared any number of times, but defined only once
+    fn push_global(&mut self, gval: GVal) -> Result<(), Diagnostic> {
+        let prev = self.ident_conflict(&gval.name).or_else(|| {
+            let mut functions = self.functions.iter();
+            functions
+                .find(|function| function.name == gval.name)
+                .map(|function| function.span)
+        });
+        if let Some(prev) = prev {
+            return Err(Diagnostic::error(
+                E_SYNTAX,
+                &format!("redefinition of `{}`", gval.name),
+                gval.span,
+            )
+            .with_secondary(prev, "first declared here"));
+        }
+        let prev = match self.globals.iter_mut().find(|prev| prev.name == gval.name) {
+            None => {
+                self.globals.push(gval);
+                return Ok(());
+            }
+            Some(prev) => prev,
+        };
+        if prev.val_type != gval.val_type {
+            return Err(Diagnostic::error(
+                E_SYNTAX,
+                &format!("conflicting types for `{}`", gval.name),
+                gval.span,
+            )
+            .with_secondary(prev.span, "previous declaration here"));
+        }
+        if prev.init.is_some() && gval.init.is_some() {
+            return Err(Diagnostic::error(
+                E_SYNTAX,
+                &format!("redefinition of `{}`", gval.name),
+                gval.span,
+            )
+            .with_secondary(prev.span, "first declared here"));
+        }
+        if gval.init.is_some() {
+            prev.init = gval.init;
+            prev.init_span = gval.init_span;
+        }
+        Ok(())
+    }
+
+    // a function may be declared any number of times with the same type,
+    // but defined only once
+    fn declare_function(
+        &mut self,
+        name: String,
+        ty: Type,
+        span: Span,
+        defined: bool,
+    ) -> Result<(), Diagnostic> {
+        let prev = self.ident_conflict(&name).or_else(|| {
+            let mut globals = self.globals.iter();
+            globals.find(|gval| gval.name == name).map(|gval| gval.span)
+        });
+        if let Some(prev) = prev {
+            return Err(
+                Diagnostic::error(E_SYNTAX, &format!("redefinition of `{}`", name), span)
+                    .with_secondary(prev, "first declared here"),
+            );
+        }
+        let (ret, params, variadic) = match ty.clone() {
+            Type::FUNC(ret, params, variadic) => (*ret, params, variadic),
+            _ => unreachable!(),
+        };
+        let prev = match self.functions.iter_mut().find(|prev| prev.name == name) {
+            None => {
+                self.functions.push(Function {
+                    name,
+                    ret,
+                    params,
+                    variadic,
+                    defined,
+                    span,
+                });
+                return Ok(());
+            }
+            Some(prev) => prev,
+        };
+        if defined && prev.defined {
+            return Err(Diagnostic::error(
+                E_SYNTAX,
+                &format!("redefinition of function `{}`", name),
+                span,
+            )
+            .with_secondary(prev.span, "first defined here"));
+        }
+        if !prev.ty().is_compatible(&ty) {
+            return Err(Diagnostic::error(
+                E_SYNTAX,
+                &format!("conflicting types for `{}`", name),
+                span,
+            )
+            .with_label(&format!("declared as `{}`", ty))
+            .with_secondary(
+                prev.span,
+                &format!("previously declared as `{}`", prev.ty()),
+            ));
+        }
+        // a prototype fills in parameters left unspecified so far
+        if prev.ty().is_unprototyped() {
+            prev.params = params;
+            prev.variadic = variadic;
+        }
+        if defined {
+            prev.defined = true;
+            prev.span = span;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn globals(&self) -> &[GVal] {
+        &self.globals
+    }
+
+    pub(crate) fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    pub(crate) fn strings(&self) -> &[Vec<u8>] {
+        &self.strings
+    }
+
+    fn parse_func(
+        &mut self,
+        ty: Type,
+        params: Vec<Param>,
+        name: String,
+        span: Span,
+        start: Span,
+    ) -> Result<Box<Node>, Diagnostic> {
+        // the body may refer to the function itself
+        self.declare_function(name.clone(), ty, span, true)?;
+        let local = self.local.len();
+        self.local.push(Vec::new());
+        self.frame = Some(local);
+        // parameters share the scope of the function's outermost block
+        self.enter_scope();
+        let body = self.parse_body(&params);
+        self.leave_scope();
+        self.frame = None;
+        let body = body?;
+        Ok(Box::new(Node::new_leaf(
+            NodeKind::Def {
+                name,
+                args: params.len(),
+                body,
+                local,
             },
+            self.span_from(start),
+        )))
+    }
+
+    fn parse_body(&mut self, params: &[Param]) -> Result<Box<Node>, Diagnostic> {
+        for param in params {
+            match &param.name {
+                Some((name, span)) => {
+                    self.push_local(param.ty.clone(), name.clone(), *span)?;
+                }
+                None => {
+                    return Err(
+                        Diagnostic::error(E_SYNTAX, "parameter name omitted", param.span)
+                            .with_note("a function definition names all of its parameters"),
+                    )
+                }
+            }
+        }
+        let start = self.span();
+        if !self.consume_token(TokenKind::OpenCur) {
+            return Err(self.unexpected("expected function body."));
+        }
+        self.parse_block(start)
+    }
+
+    // `typedef int T, *P;` after the `typedef`: each declarator names its
+    // type instead of declaring a variable
+    fn parse_typedef(&mut self) -> Result<(), Diagnostic> {
+        let base = self.parse_base_type()?;
+        loop {
+            let (ty, name, span) = self.parse_named(&base, "expected type name")?;
+            self.declare(name, Ordinary::Typedef(ty), span)?;
+            if self.consume_token(TokenKind::SemiCol) {
+                return Ok(());
+            }
+            if !self.consume_token(TokenKind::Comma) {
+                return Err(self.missing("expected semicolon", ";"));
+            }
         }
     }
 
-    fn parse_stmt(&mut self) -> Result<Box<Node>, String> {
-        // declare new lval
-        if self.consume_token(TokenKind::Int) {
-            let val_type = self.parse_ptr(Type::INT);
-            return self.parse_var(val_type);
+    // `int a = 1, *b = &a;` in a block; one node per declarator
+    fn parse_var(&mut self) -> Result<Vec<Box<Node>>, Diagnostic> {
+        if self.consume_token(TokenKind::Typedef) {
+            self.parse_typedef()?;
+            return Ok(Vec::new());
         }
+        let tagged = self.at_tag();
+        let base = self.parse_base_type()?;
+        let mut decls = Vec::new();
+        if tagged && self.consume_token(TokenKind::SemiCol) {
+            return Ok(decls);
+        }
+        loop {
+            let (val_type, name, span) = self.parse_named(&base, "expected variable name")?;
+            if let Type::FUNC(..) = val_type {
+                // there is one table of functions, so the declaration
+                // outlives the block
+                self.declare_function(name, val_type, span, false)?;
+            } else {
+                decls.push(self.parse_local(val_type, name, span)?);
+            }
+            if self.consume_token(TokenKind::SemiCol) {
+                return Ok(decls);
+            }
+            if !self.consume_token(TokenKind::Comma) {
+                return Err(self.missing("expected semicolon", ";"));
+            }
+        }
+    }
+
+    // a local variable and its initializer
+    fn parse_local(
+        &mut self,
+        val_type: Type,
+        name: String,
+        span: Span,
+    ) -> Result<Box<Node>, Diagnostic> {
+        let (val_type, offset, init) = if let Type::ARRAY(_, 0) = val_type {
+            // the size comes from the initializer, which has to be
+            // parsed before the variable has a place in the frame
+            let init = self.parse_init(&val_type, span)?;
+            let val_type = self.complete_array(val_type, init.as_deref(), span)?;
+            Self::check_complete(&val_type, &name, span)?;
+            let offset = self.push_local(val_type.clone(), name, span)?;
+            (val_type, offset, init)
+        } else {
+            Self::check_complete(&val_type, &name, span)?;
+            // the variable is in scope in its own initializer
+            let offset = self.push_local(val_type.clone(), name, span)?;
+            let init = self.parse_init(&val_type, span)?;
+            (val_type, offset, init)
+        };
+        Ok(Box::new(Node {
+            kind: NodeKind::Declar,
+            lhs: Some(Box::new(Node::new_var(
+                NodeKind::LVAL(offset),
+                val_type,
+                span,
+            ))),
+            rhs: init,
+            span: self.span_from(span),
+            ty: None,
+        }))
+    }
+
+    fn parse_stmt(&mut self) -> Result<Box<Node>, Diagnostic> {
+        let start = self.span();
 
         // nop
         if self.consume_token(TokenKind::SemiCol) {
-            return Ok(Box::new(Node::new_leaf(NodeKind::Nop)));
+            return Ok(Box::new(Node::new_leaf(NodeKind::Nop, start)));
         }
 
         // Block
         if self.consume_token(TokenKind::OpenCur) {
-            let mut stmts = Vec::new();
-            loop {
-                match &self.curr {
-                    None => return Err("no new token".to_string()),
-                    Some(token) => match token.kind {
-                        TokenKind::CloseCur => {
-                            self.consume();
-                            return Ok(Box::new(Node::new_leaf(NodeKind::Block(stmts))));
-                        }
-                        _ => stmts.push(self.parse_stmt()?),
-                    },
-                }
-            }
+            self.enter_scope();
+            let block = self.parse_block(start);
+            self.leave_scope();
+            return block;
         }
         // if else
         if self.consume_token(TokenKind::If) {
             if !self.consume_token(TokenKind::OpenParen) {
-                return Err("expected open parenthesis".to_string());
+                return Err(self.unexpected("expected open parenthesis"));
             }
             let expr = self.parse_expr()?;
             if !self.consume_token(TokenKind::CloseParen) {
-                return Err("expected close parenthesis".to_string());
+                return Err(self.missing("expected close parenthesis", ")"));
             }
             let lhs = self.parse_stmt()?;
             if self.consume_token(TokenKind::Else) {
                 let rhs = self.parse_stmt()?;
-                return Ok(Box::new(Node::new(NodeKind::If(expr), lhs, rhs)));
+                return Ok(Box::new(Node {
+                    kind: NodeKind::If(expr),
+                    lhs: Some(lhs),
+                    rhs: Some(rhs),
+                    span: self.span_from(start),
+                    ty: None,
+                }));
             }
             return Ok(Box::new(Node {
                 kind: NodeKind::If(expr),
                 lhs: Some(lhs),
                 rhs: None,
+                span: self.span_from(start),
+                ty: None,
             }));
         }
         // while statement
         if self.consume_token(TokenKind::While) {
             if !self.consume_token(TokenKind::OpenParen) {
-                return Err("expected open parenthesis".to_string());
+                return Err(self.unexpected("expected open parenthesis"));
             }
             let expr = self.parse_expr()?;
             if !self.consume_token(TokenKind::CloseParen) {
-                return Err("expected close parenthesis".to_string());
+                return Err(self.missing("expected close parenthesis", ")"));
             }
-            let stmt = self.parse_stmt()?;
+            let stmt = self.parse_loop_body()?;
             return Ok(Box::new(Node {
                 kind: NodeKind::While,
                 lhs: Some(expr),
                 rhs: Some(stmt),
+                span: self.span_from(start),
+                ty: None,
             }));
         }
         // for statement
         if self.consume_token(TokenKind::For) {
             if !self.consume_token(TokenKind::OpenParen) {
-                return Err("expected open parenthesis".to_string());
+                return Err(self.unexpected("expected open parenthesis"));
             }
             let init = if self.peek_token(TokenKind::SemiCol) {
-                Box::new(Node::new_leaf(NodeKind::Nop))
+                Box::new(Node::new_leaf(NodeKind::Nop, self.span()))
             } else {
                 self.parse_expr()?
             };
             if !self.consume_token(TokenKind::SemiCol) {
-                return Err("expected semicolon".to_string());
+                return Err(self.missing("expected semicolon", ";"));
             }
             let end = if self.peek_token(TokenKind::SemiCol) {
-                Box::new(Node::new_leaf(NodeKind::Nop))
+                Box::new(Node::new_leaf(NodeKind::Nop, self.span()))
             } else {
                 self.parse_expr()?
             };
             if !self.consume_token(TokenKind::SemiCol) {
-                return Err("expected semicolon".to_string());
+                return Err(self.missing("expected semicolon", ";"));
             }
-            let inc = if self.peek_token(TokenKind::SemiCol) {
-                Box::new(Node::new_leaf(NodeKind::Nop))
+            let inc = if self.peek_token(TokenKind::CloseParen) {
+                Box::new(Node::new_leaf(NodeKind::Nop, self.span()))
             } else {
                 self.parse_expr()?
             };
             if !self.consume_token(TokenKind::CloseParen) {
-                return Err("expected close parenthesis".to_string());
+                return Err(self.missing("expected close parenthesis", ")"));
             }
-            let stmt = self.parse_stmt()?;
+            let stmt = self.parse_loop_body()?;
             return Ok(Box::new(Node {
                 kind: NodeKind::For { init, end, inc },
                 lhs: Some(stmt),
                 rhs: None,
+                span: self.span_from(start),
+                ty: None,
             }));
         }
 
+        // switch statement
+        if self.consume_token(TokenKind::Switch) {
+            if !self.consume_token(TokenKind::OpenParen) {
+                return Err(self.unexpected("expected open parenthesis"));
+            }
+            let expr = self.parse_expr()?;
+            if !self.consume_token(TokenKind::CloseParen) {
+                return Err(self.missing("expected close parenthesis", ")"));
+            }
+            self.switches.push(Cases::default());
+            let body = self.parse_loop_body();
+            let cases = self.switches.pop().unwrap();
+            return Ok(Box::new(Node {
+                kind: NodeKind::Switch {
+                    cases: cases.values,
+                    default: cases.default.is_some(),
+                },
+                lhs: Some(expr),
+                rhs: Some(body?),
+                span: self.span_from(start),
+                ty: None,
+            }));
+        }
+        // labels of the enclosing switch
+        if self.consume_token(TokenKind::Case) {
+            let expr = self.parse_expr()?;
+            let value = match Self::eval_const(&expr) {
+                Some(value) => value,
+                None => {
+                    return Err(Diagnostic::error(
+                        E_SYNTAX,
+                        "case label is not a compile-time constant",
+                        expr.span,
+                    ))
+                }
+            };
+            if !self.consume_token(TokenKind::Colon) {
+                return Err(self.missing("expected colon", ":"));
+            }
+            let label = self.span_from(start);
+            let cases = match self.switches.last_mut() {
+                Some(cases) => cases,
+                None => return Err(Self::outside_switch("case", label)),
+            };
+            // duplicates are only known once the value is converted to the
+            // type of the condition
+            cases.values.push((value, label));
+            let id = cases.values.len() - 1;
+            let stmt = self.parse_stmt()?;
+            return Ok(Box::new(Node {
+                lhs: Some(stmt),
+                ..Node::new_leaf(NodeKind::Case(id), label)
+            }));
+        }
+        if self.consume_token(TokenKind::Default) {
+            if !self.consume_token(TokenKind::Colon) {
+                return Err(self.missing("expected colon", ":"));
+            }
+            let label = self.span_from(start);
+            let cases = match self.switches.last_mut() {
+                Some(cases) => cases,
+                None => return Err(Self::outside_switch("default", label)),
+            };
+            if let Some(prev) = cases.default {
+                return Err(Diagnostic::error(
+                    E_SYNTAX,
+                    "multiple default labels in one switch",
+                    label,
+                )
+                .with_secondary(prev, "previous default here"));
+            }
+            cases.default = Some(label);
+            let stmt = self.parse_stmt()?;
+            return Ok(Box::new(Node {
+                lhs: Some(stmt),
+                ..Node::new_leaf(NodeKind::Default, label)
+            }));
+        }
+        // break out of the enclosing loop or switch
+        if self.consume_token(TokenKind::Break) {
+            if self.breakable == 0 {
+                return Err(Diagnostic::error(
+                    E_SYNTAX,
+                    "`break` outside of a loop or switch",
+                    start,
+                ));
+            }
+            if !self.consume_token(TokenKind::SemiCol) {
+                return Err(self.missing("expected semicolon", ";"));
+            }
+            return Ok(Box::new(Node::new_leaf(NodeKind::Break, start)));
+        }
+
         // return
         if self.consume_token(TokenKind::Return) {
             let expr = self.parse_expr()?;
             if !self.consume_token(TokenKind::SemiCol) {
-                return Err("expected semicolon".to_string());
+                return Err(self.missing("expected semicolon", ";"));
             }
             return Ok(Box::new(Node {
                 kind: NodeKind::Return,
                 lhs: None,
                 rhs: Some(expr),
+                span: self.span_from(start),
+                ty: None,
             }));
         }
         let expr = self.parse_expr()?;
         if !self.consume_token(TokenKind::SemiCol) {
-            return Err("expected semicolon".to_string());
+            return Err(self.missing("expected semicolon", ";"));
         }
         Ok(expr)
     }
 
-    fn parse_expr(&mut self) -> Result<Box<Node>, String> {
+    // the body of a loop or switch, which `break` leaves
+    fn parse_loop_body(&mut self) -> Result<Box<Node>, Diagnostic> {
+        self.breakable += 1;
+        let body = self.parse_stmt();
+        self.breakable -= 1;
+        body
+    }
+
+    fn outside_switch(label: &str, span: Span) -> Diagnostic {
+        Diagnostic::error(
+            E_SYNTAX,
+            &format!("`{}` label outside of a switch", label),
+            span,
+        )
+    }
+
+    // the statements of a block up to its `}`; `start` is the `{`
+    fn parse_block(&mut self, start: Span) -> Result<Box<Node>, Diagnostic> {
+        let mut stmts = Vec::new();
+        loop {
+            match &self.curr {
+                None => {
+                    return Err(self
+                        .missing("expected close brace", "}")
+                        .with_secondary(start, "to match this `{`"))
+                }
+                Some(token) => match token.kind {
+                    TokenKind::CloseCur => {
+                        self.consume();
+                        return Ok(Box::new(Node::new_leaf(
+                            NodeKind::Block(stmts),
+                            self.span_from(start),
+                        )));
+                    }
+                    _ => {
+                        let stmt_start = self.span();
+                        // declarations are block items, not statements
+                        let items = if self.at_type() {
+                            self.parse_var()
+                        } else {
+                            self.parse_stmt().map(|stmt| vec![stmt])
+                        };
+                        match items {
+                            Ok(items) => stmts.extend(items),
+                            Err(err) => {
+                                self.report(err);
+                                self.sync_stmt(stmt_start);
+                            }
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Box<Node>, Diagnostic> {
         let node = self.parse_assign()?;
         Ok(node)
     }
 
-    fn parse_assign(&mut self) -> Result<Box<Node>, String> {
+    fn parse_assign(&mut self) -> Result<Box<Node>, Diagnostic> {
         let mut node = self.parse_equality()?;
         loop {
             match &self.curr {
@@ -383,7 +2014,7 @@ impl Parser {
         }
     }
 
-    fn parse_equality(&mut self) -> Result<Box<Node>, String> {
+    fn parse_equality(&mut self) -> Result<Box<Node>, Diagnostic> {
         let mut node = self.parse_relation()?;
         loop {
             match &self.curr {
@@ -405,7 +2036,7 @@ impl Parser {
         }
     }
 
-    fn parse_relation(&mut self) -> Result<Box<Node>, String> {
+    fn parse_relation(&mut self) -> Result<Box<Node>, Diagnostic> {
         let mut node = self.parse_add()?;
         loop {
             match &self.curr {
@@ -437,7 +2068,7 @@ impl Parser {
         }
     }
 
-    fn parse_add(&mut self) -> Result<Box<Node>, String> {
+    fn parse_add(&mut self) -> Result<Box<Node>, Diagnostic> {
         let mut node = self.parse_mul()?;
         loop {
             match &self.curr {
@@ -459,7 +2090,7 @@ impl Parser {
         }
     }
 
-    fn parse_mul(&mut self) -> Result<Box<Node>, String> {
+    fn parse_mul(&mut self) -> Result<Box<Node>, Diagnostic> {
         let mut node = self.parse_unary()?;
         loop {
             match &self.curr {
@@ -481,100 +2112,263 @@ impl Parser {
         }
     }
 
-    fn parse_unary(&mut self) -> Result<Box<Node>, String> {
+    fn parse_unary(&mut self) -> Result<Box<Node>, Diagnostic> {
         match &self.curr {
-            None => return Err("No new token".to_string()),
+            None => return Err(self.unexpected("expected expression")),
             Some(token) => match token.kind {
                 TokenKind::Add => {
+                    let op = token.span;
                     self.consume();
                     let rhs = self.parse_unary()?;
-                    return Ok(Box::new(Node::new_unary(NodeKind::ADD, rhs)));
+                    return Ok(Box::new(Node::new_unary(NodeKind::ADD, rhs, op)));
                 }
                 TokenKind::Minus => {
+                    let op = token.span;
                     self.consume();
                     let rhs = self.parse_unary()?;
-                    return Ok(Box::new(Node::new_unary(NodeKind::SUB, rhs)));
+                    return Ok(Box::new(Node::new_unary(NodeKind::SUB, rhs, op)));
                 }
                 TokenKind::Star => {
+                    let op = token.span;
                     self.consume();
-                    let rhs = self.parse_primary()?;
-                    if let NodeKind::LVAL(_) = rhs.kind {
-                        return Ok(Box::new(Node::new_unary(NodeKind::Deref, rhs)));
-                    } else {
-                        return Err("expected lval".to_string());
-                    }
+                    let rhs = self.parse_unary()?;
+                    return Ok(Box::new(Node::new_unary(NodeKind::Deref, rhs, op)));
                 }
                 TokenKind::And => {
+                    let op = token.span;
                     self.consume();
-                    let rhs = self.parse_primary()?;
-                    if let NodeKind::LVAL(_) = rhs.kind {
-                        return Ok(Box::new(Node::new_unary(NodeKind::Addr, rhs)));
-                    } else {
-                        return Err("expected lval".to_string());
-                    }
+                    let rhs = self.parse_unary()?;
+                    return Ok(Box::new(Node::new_unary(NodeKind::Addr, rhs, op)));
                 }
-                _ => return self.parse_primary(),
+                _ => return self.parse_postfix(),
             },
         }
     }
 
-    fn parse_primary(&mut self) -> Result<Box<Node>, String> {
+    // subscripts, member accesses and calls after a primary expression
+    fn parse_postfix(&mut self) -> Result<Box<Node>, Diagnostic> {
+        let mut node = self.parse_primary()?;
+        loop {
+            let start = self.span();
+            if self.consume_token(TokenKind::OpenParen) {
+                let argv = self.parse_argv()?;
+                let span = self.span_from(node.span);
+                node = Box::new(Node {
+                    lhs: Some(node),
+                    ..Node::new_leaf(NodeKind::Call(argv), span)
+                });
+                continue;
+            }
+            if self.consume_token(TokenKind::Dot) {
+                let (name, _) = self.parse_name("expected member name")?;
+                let span = self.span_from(node.span);
+                node = Box::new(Node {
+                    lhs: Some(node),
+                    ..Node::new_leaf(NodeKind::Member(name), span)
+                });
+                continue;
+            }
+            if self.consume_token(TokenKind::Arrow) {
+                let (name, _) = self.parse_name("expected member name")?;
+                let span = self.span_from(node.span);
+                let deref = Box::new(Node::new_unary(NodeKind::Deref, node, start));
+                node = Box::new(Node {
+                    lhs: Some(deref),
+                    ..Node::new_leaf(NodeKind::Member(name), span)
+                });
+                continue;
+            }
+            if !self.consume_token(TokenKind::OpenSquare) {
+                return Ok(node);
+            }
+            let index = self.parse_expr()?;
+            if !self.consume_token(TokenKind::CloseSquare) {
+                return Err(self
+                    .missing("expected close bracket", "]")
+                    .with_secondary(start, "to match this `[`"));
+            }
+            let span = self.span_from(node.span);
+            node = Box::new(Node {
+                span,
+                ..Node::new(NodeKind::Subscript, node, index)
+            });
+        }
+    }
+
+    // `a, b)` after the open parenthesis of a call
+    fn parse_argv(&mut self) -> Result<Vec<Box<Node>>, Diagnostic> {
+        let mut argv = Vec::new();
+        if self.consume_token(TokenKind::CloseParen) {
+            return Ok(argv);
+        }
+        loop {
+            argv.push(self.parse_expr()?);
+            if self.consume_token(TokenKind::CloseParen) {
+                return Ok(argv);
+            }
+            if !self.consume_token(TokenKind::Comma) {
+                return Err(self.missing("expected close parenthesis", ")"));
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Box<Node>, Diagnostic> {
+        let start = self.span();
         match &self.curr {
             Some(token) => match token.kind.to_owned() {
                 TokenKind::OpenParen => {
                     self.consume();
+                    // `(int *)p` converts what follows, which binds as
+                    // tightly as a unary operand
+                    if self.at_type() {
+                        let ty = self.parse_type_name()?;
+                        if !self.consume_token(TokenKind::CloseParen) {
+                            return Err(self
+                                .missing("expected close parenthesis", ")")
+                                .with_secondary(start, "to match this `(`"));
+                        }
+                        let operand = self.parse_unary()?;
+                        let span = self.span_from(start);
+                        return Ok(Box::new(Node {
+                            rhs: Some(operand),
+                            ..Node::new_var(NodeKind::Cast, ty, span)
+                        }));
+                    }
                     let node = self.parse_expr()?;
                     if !self.consume_token(TokenKind::CloseParen) {
-                        return Err("invalid parentheses".to_string());
+                        return Err(self
+                            .missing("invalid parentheses", ")")
+                            .with_secondary(start, "to match this `(`"));
                     } else {
                         Ok(node)
                     }
                 }
+                TokenKind::Character(value) => {
+                    self.consume();
+                    Ok(Box::new(Node::new_leaf(NodeKind::NUM(value), start)))
+                }
+                TokenKind::Str(mut bytes) => {
+                    self.consume();
+                    // adjacent literals are one string
+                    while let Some(Token {
+                        kind: TokenKind::Str(next),
+                        ..
+                    }) = self.curr.as_deref()
+                    {
+                        bytes.extend_from_slice(next);
+                        self.consume();
+                    }
+                    let ty = Type::ARRAY(Box::new(Type::CHAR), bytes.len() + 1);
+                    self.strings.push(bytes);
+                    let id = self.strings.len() - 1;
+                    Ok(Box::new(Node::new_var(
+                        NodeKind::Str(id),
+                        ty,
+                        self.span_from(start),
+                    )))
+                }
                 TokenKind::Num(s) => {
                     self.consume();
-                    Ok(Box::new(Node::new_leaf(NodeKind::NUM(s.parse().unwrap()))))
+                    match s.parse() {
+                        Ok(num) => Ok(Box::new(Node::new_leaf(NodeKind::NUM(num), start))),
+                        Err(_) => Err(Diagnostic::error(
+                            E_TOKEN,
+                            "integer literal is too large",
+                            start,
+                        )
+                        .with_note(&format!("the largest supported value is {}", i64::MAX))),
+                    }
                 }
                 TokenKind::Ident(name) => {
                     self.consume();
-                    // function call
-                    if self.consume_token(TokenKind::OpenParen) {
-                        let mut argv = Vec::new();
-                        if self.consume_token(TokenKind::CloseParen) {
-                            return Ok(Box::new(Node::new_leaf(NodeKind::Func { name, argv })));
+                    // a call by name, unless a variable holds the function
+                    let var = self.find_lval(&name).is_some()
+                        || self.globals.iter().any(|gval| gval.name == name);
+                    if !var && self.consume_token(TokenKind::OpenParen) {
+                        let argv = self.parse_argv()?;
+                        return Ok(Box::new(Node::new_leaf(
+                            NodeKind::Func { name, argv },
+                            self.span_from(start),
+                        )));
+                    }
+
+                    match self.find_ident(&name) {
+                        Some(Ordinary::Enumerator(value)) => {
+                            return Ok(Box::new(Node::new_leaf(NodeKind::NUM(value), start)))
                         }
-                        loop {
-                            let arg = self.parse_expr()?;
-                            argv.push(arg);
-                            if self.consume_token(TokenKind::CloseParen) {
-                                return Ok(Box::new(Node::new_leaf(NodeKind::Func { name, argv })));
-                            }
-                            if self.consume_token(TokenKind::Comma) {
-                                continue;
-                            }
+                        Some(Ordinary::Typedef(_)) => {
+                            return Err(Diagnostic::error(
+                                E_SYNTAX,
+                                &format!("unexpected type name `{}`", name),
+                                start,
+                            )
+                            .with_label("expected expression"))
                         }
+                        None => {}
                     }
-
-                    if let Some(offset) = self.find_lval(&name) {
-                        return Ok(Box::new(Node::new_leaf(NodeKind::LVAL(offset))));
+                    if let Some((offset, ty)) = self.find_lval(&name) {
+                        return Ok(Box::new(Node::new_var(NodeKind::LVAL(offset), ty, start)));
+                    } else if let Some(gval) = self.globals.iter().find(|gval| gval.name == name) {
+                        let ty = gval.val_type.clone();
+                        return Ok(Box::new(Node::new_var(NodeKind::GVAL(name), ty, start)));
+                    } else if let Some(function) = self.functions.iter().find(|f| f.name == name) {
+                        // a function designator, as in `fp = f` or `&f`
+                        let ty = function.ty();
+                        return Ok(Box::new(Node::new_var(NodeKind::GVAL(name), ty, start)));
                     } else {
-                        return Err("variable not defined".to_string());
+                        return Err(Diagnostic::error(
+                            E_UNDEFINED,
+                            &format!("variable `{}` not defined", name),
+                            start,
+                        )
+                        .with_label("not found in this scope"));
                     }
                 }
-                _ => Err("unexpected token".to_string()),
+                _ => Err(self.unexpected("expected expression")),
             },
-            None => Err("no new token".to_string()),
+            None => Err(self.unexpected("expected expression")),
         }
     }
 
+    // the next token; a malformed one is reported as soon as it is read,
+    // and then left for the grammar to stumble on
     fn next_token(&mut self) -> Option<Box<Token>> {
-        self.lexer.next()
-    }
-    fn find_lval(&self, ident: &str) -> Option<u8> {
-        let cur_local = self.local.last().unwrap();
-        let lval = cur_local.iter().find(|lval| lval.name == ident)?;
-        Some(lval.offset)
+        let token = self.lexer.next()?;
+        if let TokenKind::Error(message) = &token.kind {
+            let err = Diagnostic::error(E_TOKEN, message, token.span).with_label("not valid here");
+            self.errors.push(err);
+        }
+        Some(token)
     }
 
+    // record an error recovered from, unless it is a syntax error at a
+    // malformed token, or just before one, which would only repeat the
+    // error `next_token` gave for it
+    fn report(&mut self, err: Diagnostic) {
+        let at_invalid = matches!(
+            self.curr.as_ref().map(|token| &token.kind),
+            Some(TokenKind::Error(_))
+        );
+        let span = err.primary.span;
+        if at_invalid && err.code == E_SYNTAX && (span == self.span() || span == self.prev.after())
+        {
+            return;
+        }
+        self.errors.push(err);
+    }
+    // the innermost visible variable of the current function; `None` at
+    // file scope
+    fn find_lval(&self, ident: &str) -> Option<(usize, Type)> {
+        let cur_local = &self.local[self.frame?];
+        let lval = self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .map(|&index| &cur_local[index])
+            .find(|lval| lval.name == ident)?;
+        Some((lval.offset, lval.val_type.clone()))
+    }
 
     fn peek_token(&mut self, expected: TokenKind) -> bool {
         match &self.curr {
@@ -601,30 +2395,156 @@ impl Parser {
         }
     }
 
-    pub fn get_local_size(&self, id: usize) -> usize {
-        self.local[id].len()
+    // a block opens a scope for both variables and struct tags
+    fn enter_scope(&mut self) {
+        self.scopes.push(Vec::new());
+        self.names.push(Scope::default());
+    }
+
+    fn leave_scope(&mut self) {
+        self.scopes.pop();
+        self.names.pop();
+    }
+
+    // the innermost visible struct, union or enum with this tag
+    fn find_tag(&self, tag: &str) -> Option<Tag> {
+        self.names
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.tags.iter())
+            .find(|def| def.name() == Some(tag))
+            .cloned()
+    }
+
+    // the enumerator or typedef a name refers to, unless a variable of an
+    // inner scope hides it; the variables of `names[depth]` are in
+    // `scopes[depth - 1]`
+    fn find_ident(&self, name: &str) -> Option<Ordinary> {
+        for depth in (0..self.names.len()).rev() {
+            let scope = &self.names[depth];
+            if let Some((_, ident, _)) = scope.idents.iter().find(|(prev, ..)| prev == name) {
+                return Some(ident.clone());
+            }
+            let frame = self.frame.map(|frame| &self.local[frame]);
+            let hidden = match (depth.checked_sub(1), frame) {
+                (Some(block), Some(frame)) => self
+                    .scopes
+                    .get(block)
+                    .is_some_and(|vars| vars.iter().any(|&index| frame[index].name == name)),
+                _ => false,
+            };
+            if hidden {
+                return None;
+            }
+        }
+        None
+    }
+
+    // the enumerator or typedef of the innermost scope that a new name would
+    // redefine
+    fn ident_conflict(&self, name: &str) -> Option<Span> {
+        let scope = self.names.last().unwrap();
+        scope
+            .idents
+            .iter()
+            .find(|(prev, ..)| prev == name)
+            .map(|(.., span)| *span)
     }
 
-    fn push_local(&mut self, val_type: Type, name: String) -> u8 {
+    // the type a typedef name at the cursor stands for
+    fn find_typedef(&self) -> Option<Type> {
+        match self.curr.as_ref().map(|token| &token.kind) {
+            Some(TokenKind::Ident(name)) => match self.find_ident(name)? {
+                Ordinary::Typedef(ty) => Some(ty),
+                Ordinary::Enumerator(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    // bytes of stack the locals of a function need, before any padding
+    pub fn frame_size(&self, id: usize) -> usize {
+        self.local[id].last().map_or(0, |lval| lval.offset)
+    }
+
+    // where the prolog spills the register arguments, and their types
+    pub fn params(&self, id: usize, args: usize) -> Vec<(usize, Type)> {
+        self.local[id]
+            .iter()
+            .take(args)
+            .map(|lval| (lval.offset, lval.val_type.clone()))
+            .collect()
+    }
+
+    // declare a variable in the innermost scope; every variable of a
+    // function gets its own slot in the frame, even once it is out of scope
+    fn push_local(
+        &mut self,
+        val_type: Type,
+        name: String,
+        span: Span,
+    ) -> Result<usize, Diagnostic> {
         let id = self.local.len() - 1;
-        if self.local[id].len() == 0 {
-            self.local[id].push(LVal::new(name, val_type, 8));
-            return 8;
-        } else {
-            let offset = self.local[id].last().unwrap().offset + 8;
-            self.local[id].push(LVal::new(name, val_type, offset));
-            return offset;
+        let scope = self.scopes.last().unwrap();
+        let prev = scope
+            .iter()
+            .find(|&&index| self.local[id][index].name == name)
+            .map(|&index| self.local[id][index].span);
+        if let Some(prev) = prev.or_else(|| self.ident_conflict(&name)) {
+            return Err(
+                Diagnostic::error(E_SYNTAX, &format!("redefinition of `{}`", name), span)
+                    .with_secondary(prev, "first declared here"),
+            );
+        }
+        // the frame grows down, so a variable starts `offset` bytes below
+        // rbp and ends at the start of the previous one
+        let offset = align_to(self.frame_size(id) + val_type.size(), val_type.align());
+        if align_to(offset, 16) > MAX_OBJECT_SIZE {
+            return Err(Diagnostic::error(
+                E_SYNTAX,
+                &format!("`{}` does not fit in the stack frame", name),
+                span,
+            )
+            .with_note(&format!(
+                "the locals of a function may take at most {} bytes",
+                MAX_OBJECT_SIZE
+            )));
         }
+        let scope = self.scopes.last_mut().unwrap();
+        scope.push(self.local[id].len());
+        self.local[id].push(LVal::new(name, val_type, offset, span));
+        Ok(offset)
     }
 }
 
+fn is_type_keyword(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Int
+            | TokenKind::Char
+            | TokenKind::Short
+            | TokenKind::Long
+            | TokenKind::Signed
+            | TokenKind::Unsigned
+            | TokenKind::Struct
+            | TokenKind::Union
+            | TokenKind::Enum
+            | TokenKind::Typedef
+    )
+}
+
 //------------------------------------------------------------------------
 //------------------------------------------------------------------------
 
 #[cfg(test)]
 impl Parser {
+    pub fn load(src: String) -> Self {
+        Self::with_file(src, 0)
+    }
     fn new_stack(&mut self) {
+        self.frame = Some(self.local.len());
         self.local.push(Vec::new());
+        self.enter_scope();
     }
     fn init(&mut self) {
         self.consume();
@@ -756,10 +2676,10 @@ fn stmt_test() {
         let first = stmts[0].clone();
         let second = stmts[1].clone();
         assert_eq!(first.kind, NodeKind::Declar);
-        assert_eq!(first.lhs.unwrap().kind, NodeKind::LVAL(8));
+        assert_eq!(first.lhs.unwrap().kind, NodeKind::LVAL(4));
         assert_eq!(first.rhs, None);
         assert_eq!(second.kind, NodeKind::Assign);
-        assert_eq!(second.lhs.unwrap().kind, NodeKind::LVAL(8));
+        assert_eq!(second.lhs.unwrap().kind, NodeKind::LVAL(4));
         assert_eq!(second.rhs.unwrap().kind, NodeKind::NUM(42));
     } else {
         panic!("expect block statement");
@@ -772,8 +2692,12 @@ fn assign_test() {
     let mut parser = Parser::load(code);
     parser.consume();
     parser.new_stack();
-    parser.push_local(Type::INT, "a".to_string());
-    parser.push_local(Type::INT, "b".to_string());
+    parser
+        .push_local(Type::INT, "a".to_string(), Span::default())
+        .unwrap();
+    parser
+        .push_local(Type::INT, "b".to_string(), Span::default())
+        .unwrap();
     let node = parser.parse_stmt().unwrap();
     if let NodeKind::Block(stmts) = node.kind {
         assert_eq!(stmts.len(), 3);
@@ -781,13 +2705,13 @@ fn assign_test() {
         let second = stmts[1].clone();
         let third = stmts[2].clone();
         assert_eq!(first.kind, NodeKind::Assign);
-        assert_eq!(first.lhs.unwrap().kind, NodeKind::LVAL(8));
+        assert_eq!(first.lhs.unwrap().kind, NodeKind::LVAL(4));
         assert_eq!(first.rhs.unwrap().kind, NodeKind::NUM(42));
         assert_eq!(second.kind, NodeKind::Assign);
-        assert_eq!(second.lhs.unwrap().kind, NodeKind::LVAL(16));
+        assert_eq!(second.lhs.unwrap().kind, NodeKind::LVAL(8));
         assert_eq!(second.rhs.unwrap().kind, NodeKind::NUM(31));
         assert_eq!(third.kind, NodeKind::Assign);
-        assert_eq!(third.lhs.unwrap().kind, NodeKind::LVAL(8));
+        assert_eq!(third.lhs.unwrap().kind, NodeKind::LVAL(4));
         assert_eq!(third.rhs.unwrap().kind, NodeKind::NUM(31));
     } else {
         panic!("expect block statement");
@@ -843,7 +2767,9 @@ fn for_test() {
     let mut parser = Parser::load(code);
     parser.consume();
     parser.new_stack();
-    parser.push_local(Type::INT, "a".to_string());
+    parser
+        .push_local(Type::INT, "a".to_string(), Span::default())
+        .unwrap();
     let stmt = parser.parse_stmt().unwrap();
     if let NodeKind::For { init, end, inc } = stmt.kind {
         assert_eq!(init.kind, NodeKind::Assign);
@@ -892,7 +2818,9 @@ fn if_block_test() {
     let mut parser = Parser::load(code);
     parser.consume();
     parser.new_stack();
-    parser.push_local(Type::INT, "a".to_string());
+    parser
+        .push_local(Type::INT, "a".to_string(), Span::default())
+        .unwrap();
     let node = parser.parse_stmt().unwrap();
     if let NodeKind::If(_) = node.kind {
         let block = node.lhs.unwrap();
@@ -1014,7 +2942,7 @@ fn dec_test() {
         local,
     } = func.kind
     {
-        assert_eq!(parser.get_local_size(local), 1);
+        assert_eq!(parser.frame_size(local), 4);
         assert_eq!(parser.local[0][0].name, "a");
     } else {
         panic!("expected function def");
@@ -1042,11 +2970,875 @@ fn pptr_test() {
             assert_eq!(*val, Type::INT);
         } else {
             panic!("expected pointer ");
-        } 
+        }
     } else {
         panic!("expected pointer to pointer");
     }
+}
+
+#[test]
+fn span_test() {
+    let code = String::from("{int a;\n a = 4 + 2;}");
+    let mut parser = Parser::load(code);
+    parser.init();
+    let node = parser.parse_stmt().unwrap();
+    assert_eq!(node.span, Span::new(0, 0, 20, 1, 1));
+    if let NodeKind::Block(stmts) = node.kind {
+        let assign = stmts[1].clone();
+        assert_eq!(assign.span, Span::new(0, 9, 9, 2, 2));
+        let add = assign.rhs.unwrap();
+        assert_eq!(add.span, Span::new(0, 13, 5, 2, 6));
+    } else {
+        panic!("expected block")
+    }
+}
+
+#[test]
+fn missing_semicolon_test() {
+    let code = String::from("int main(){return 0}");
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    let err = &errors[0];
+    assert_eq!(err.code, E_SYNTAX);
+    assert_eq!(err.message, "expected semicolon");
+    assert_eq!(err.primary.span, Span::new(0, 19, 0, 1, 20));
+    assert_eq!(err.fixits[0].replacement, ";");
+}
+
+#[test]
+fn recovery_test() {
+    let code = String::from(
+        "int foo(){int a; a = ; return a;} int bar(int x { return 1; } int main(){1 + ; if (1) {2} return 3;}",
+    );
+    let mut parser = Parser::load(code);
+    let (functions, errors) = parser.parse();
+    assert_eq!(errors.len(), 4);
+    assert_eq!(errors[0].message, "expected expression");
+    assert_eq!(errors[1].message, "expected close parenthesis");
+    assert_eq!(errors[2].message, "expected expression");
+    assert_eq!(errors[3].message, "expected semicolon");
+    // `bar` is dropped, the other definitions survive with the bad
+    // statements skipped
+    assert_eq!(functions.len(), 2);
+    if let NodeKind::Def { name, body, .. } = functions[1].kind.clone() {
+        assert_eq!(name, "main");
+        if let NodeKind::Block(stmts) = body.kind {
+            assert_eq!(stmts.len(), 2);
+            assert_eq!(stmts[1].kind, NodeKind::Return);
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+}
+
+#[test]
+fn invalid_token_test() {
+    let code = String::from("int main(){int a; a = 1 @ 2; return 99999999999999999999;}");
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].code, E_TOKEN);
+    assert_eq!(errors[0].message, "unknown character `@`");
+    assert_eq!(errors[1].message, "integer literal is too large");
+}
+
+#[test]
+fn global_test() {
+    let code = String::from("int a, *p = &a; int b = 2 * 3; int a; int main(){return a + b;}");
+    let mut parser = Parser::load(code);
+    let functions = parser.run().unwrap();
+    assert_eq!(functions.len(), 1);
+    let globals = parser.globals();
+    assert_eq!(globals.len(), 3);
+    assert_eq!(globals[0].init, None);
+    assert_eq!(
+        globals[1].init,
+        Some(Init::Addr {
+            name: "a".to_string(),
+            offset: 0,
+            ty: Type::INT
+        })
+    );
+    assert_eq!(globals[2].init, Some(Init::Num(6)));
+    // an array is the address of its first element
+    let code = String::from("int a[3]; int *p = a; int *q = &a[2]; int (*r)[3] = &a;");
+    let mut parser = Parser::load(code);
+    parser.run().unwrap();
+    let inits: Vec<_> = parser.globals()[1..]
+        .iter()
+        .map(|global| global.init.clone().unwrap())
+        .collect();
+    let addr = |offset, ty| Init::Addr {
+        name: "a".to_string(),
+        offset,
+        ty,
+    };
+    assert_eq!(
+        inits,
+        vec![
+            addr(0, Type::INT),
+            addr(8, Type::INT),
+            addr(0, Type::ARRAY(Box::new(Type::INT), 3)),
+        ]
+    );
+    if let NodeKind::Def { body, .. } = functions[0].kind.clone() {
+        if let NodeKind::Block(stmts) = body.kind {
+            let add = stmts[0].rhs.clone().unwrap();
+            assert_eq!(add.lhs.unwrap().kind, NodeKind::GVAL("a".to_string()));
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+}
+
+#[test]
+fn global_error_test() {
+    let code = String::from("int a = 1; int a = 2; int *a; int c = a; int main(){return 0;}");
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    assert_eq!(errors.len(), 3);
+    assert_eq!(errors[0].message, "redefinition of `a`");
+    assert_eq!(errors[0].secondary[0].span, Span::new(0, 4, 1, 1, 5));
+    assert_eq!(errors[1].message, "conflicting types for `a`");
+    assert_eq!(
+        errors[2].message,
+        "initializer element is not a compile-time constant"
+    );
+}
+
+#[test]
+fn scope_test() {
+    let code = String::from("int main(int a){int b; {int a; int c; a = 1;} {int c;} b = a;}");
+    let mut parser = Parser::load(code);
+    let functions = parser.run().unwrap();
+    // one slot per declaration, shadowed or not
+    assert_eq!(parser.frame_size(0), 20);
+    if let NodeKind::Def { body, .. } = functions[0].kind.clone() {
+        if let NodeKind::Block(stmts) = body.kind {
+            if let NodeKind::Block(inner) = stmts[1].kind.clone() {
+                let assign = inner[2].clone();
+                assert_eq!(assign.lhs.unwrap().kind, NodeKind::LVAL(12));
+            } else {
+                panic!("expected block");
+            }
+            let assign = stmts[3].clone();
+            assert_eq!(assign.lhs.unwrap().kind, NodeKind::LVAL(8));
+            assert_eq!(assign.rhs.unwrap().kind, NodeKind::LVAL(4));
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+}
+
+#[test]
+fn redefinition_test() {
+    let code = String::from("int main(int a){int a; int b; {int c;} c = 1; int b;}");
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    assert_eq!(errors.len(), 3);
+    assert_eq!(errors[0].message, "redefinition of `a`");
+    assert_eq!(errors[0].secondary[0].span, Span::new(0, 13, 1, 1, 14));
+    assert_eq!(errors[1].message, "variable `c` not defined");
+    assert_eq!(errors[2].message, "redefinition of `b`");
+}
+
+#[test]
+fn init_test() {
+    let code = String::from("{int a = 1, *b = &a; int c;}");
+    let mut parser = Parser::load(code);
+    parser.init();
+    let node = parser.parse_stmt().unwrap();
+    if let NodeKind::Block(stmts) = node.kind {
+        assert_eq!(stmts.len(), 3);
+        assert_eq!(stmts[0].kind, NodeKind::Declar);
+        assert_eq!(stmts[0].rhs.clone().unwrap().kind, NodeKind::NUM(1));
+        let addr = stmts[1].rhs.clone().unwrap();
+        assert_eq!(addr.kind, NodeKind::Addr);
+        assert_eq!(addr.rhs.unwrap().kind, NodeKind::LVAL(4));
+        assert_eq!(stmts[2].rhs, None);
+    } else {
+        panic!("expected block")
+    }
+    assert!(matches!(parser.local[0][1].val_type, Type::PTR(_)));
+}
+
+#[test]
+fn frame_test() {
+    // more locals than a byte of offset can address
+    let mut code = String::from("int main(){");
+    for i in 0..40 {
+        code.push_str(&format!("int v{}; ", i));
+    }
+    code.push_str("return v39;}");
+    let mut parser = Parser::load(code);
+    let functions = parser.run().unwrap();
+    assert_eq!(parser.frame_size(0), 160);
+    if let NodeKind::Def { body, .. } = functions[0].kind.clone() {
+        if let NodeKind::Block(stmts) = body.kind {
+            let ret = stmts[40].clone();
+            assert_eq!(ret.rhs.unwrap().kind, NodeKind::LVAL(160));
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+}
+
+#[test]
+fn align_test() {
+    assert_eq!(align_to(0, 16), 0);
+    assert_eq!(align_to(8, 16), 16);
+    assert_eq!(align_to(16, 16), 16);
+    assert_eq!(align_to(13, 8), 16);
+}
+
+#[test]
+fn type_display_test() {
+    let int = Type::INT;
+    let ptr = Type::PTR(Box::new(Type::INT));
+    let array = Type::ARRAY(Box::new(Type::ARRAY(Box::new(Type::INT), 3)), 2);
+    assert_eq!(int.to_string(), "int");
+    assert_eq!(ptr.to_string(), "int *");
+    assert_eq!(Type::PTR(Box::new(ptr.clone())).to_string(), "int **");
+    assert_eq!(array.to_string(), "int[2][3]");
+    assert_eq!(Type::ARRAY(Box::new(ptr), 4).to_string(), "int *[4]");
+    assert_eq!(
+        Type::PTR(Box::new(Type::ARRAY(Box::new(int), 3))).to_string(),
+        "int (*)[3]"
+    );
+}
+
+#[test]
+fn array_test() {
+    let code = String::from(
+        "int g[4][2]; int main(int p[]){int a[10], *b[3]; a[1] = p[0]; return g[1][1];}",
+    );
+    let mut parser = Parser::load(code);
+    let functions = parser.run().unwrap();
+    let g = Type::ARRAY(Box::new(Type::ARRAY(Box::new(Type::INT), 2)), 4);
+    assert_eq!(parser.globals()[0].val_type, g);
+    assert_eq!(parser.local[0][0].val_type, Type::PTR(Box::new(Type::INT)));
+    assert_eq!(parser.local[0][1].offset, 48);
+    assert_eq!(parser.local[0][2].offset, 72);
+    assert_eq!(parser.frame_size(0), 72);
+    if let NodeKind::Def { body, .. } = functions[0].kind.clone() {
+        if let NodeKind::Block(stmts) = body.kind {
+            let assign = stmts[2].clone();
+            let lhs = assign.lhs.unwrap();
+            assert_eq!(lhs.kind, NodeKind::Subscript);
+            assert_eq!(lhs.span, Span::new(0, 49, 4, 1, 50));
+            let ret = stmts[3].rhs.clone().unwrap();
+            assert_eq!(ret.kind, NodeKind::Subscript);
+            assert_eq!(ret.lhs.unwrap().kind, NodeKind::Subscript);
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+}
+
+#[test]
+fn array_error_test() {
+    let code = String::from("int main(){int a[]; int b[0]; int c[2] = 1; int d[2; return 0;}");
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    assert_eq!(errors.len(), 4);
+    assert_eq!(errors[0].message, "array size missing");
+    assert_eq!(errors[1].message, "array size must be positive");
+    assert_eq!(errors[2].message, "array initializers are not supported");
+    assert_eq!(errors[3].message, "expected close bracket");
+}
+
+#[test]
+fn array_size_error_test() {
+    let code = String::from(
+        "long g[4611686018427387904]; int h[99999999999]; long l[1000000000]; \
+         char c[2][2147483647]; \
+         struct s {int a[500000000]; int b[500000000];}; \
+         int f(){char a[2147483632]; return 0;} \
+         int main(){int a[400000000]; int b[400000000]; return 0;}",
+    );
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    assert_eq!(errors.len(), 6);
+    assert_eq!(errors[0].message, "array size is too large");
+    assert_eq!(errors[1].message, "array size is too large");
+    assert_eq!(errors[2].message, "array of 1000000000 `long` is too large");
+    assert_eq!(
+        errors[3].message,
+        "array of 2 `char[2147483647]` is too large"
+    );
+    assert_eq!(errors[4].message, "`struct s` is too large");
+    assert_eq!(errors[5].message, "`b` does not fit in the stack frame");
+}
+
+#[test]
+fn lvalue_test() {
+    let code = String::from("{int a; int *p; int **pp; *(p + 1) = 3; **pp = 1; p = &*p;}");
+    let mut parser = Parser::load(code);
+    parser.init();
+    let node = parser.parse_stmt().unwrap();
+    if let NodeKind::Block(stmts) = node.kind {
+        let lhs = stmts[3].lhs.clone().unwrap();
+        assert_eq!(lhs.kind, NodeKind::Deref);
+        assert_eq!(lhs.rhs.unwrap().kind, NodeKind::ADD);
+        let lhs = stmts[4].lhs.clone().unwrap();
+        assert_eq!(lhs.rhs.unwrap().kind, NodeKind::Deref);
+        let rhs = stmts[5].rhs.clone().unwrap();
+        assert_eq!(rhs.kind, NodeKind::Addr);
+        assert_eq!(rhs.rhs.unwrap().kind, NodeKind::Deref);
+    } else {
+        panic!("expected block")
+    }
+}
+
+#[test]
+fn function_table_test() {
+    let code = String::from("int *f(int a, int *b[2]){return b[0];} int f(){return 0;}");
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "redefinition of function `f`");
+    let f = &parser.functions()[0];
+    assert_eq!(f.ret, Type::PTR(Box::new(Type::INT)));
+    assert_eq!(
+        f.params,
+        vec![
+            Type::INT,
+            Type::PTR(Box::new(Type::PTR(Box::new(Type::INT))))
+        ]
+    );
+}
+
+#[test]
+fn char_test() {
+    let code = String::from(
+        "char g, *s; unsigned char f(signed char c, unsigned char *u){char a[3], b = 'x'; return a[0];}",
+    );
+    let mut parser = Parser::load(code);
+    let functions = parser.run().unwrap();
+    assert_eq!(parser.globals()[0].val_type, Type::CHAR);
+    assert_eq!(
+        parser.globals()[1].val_type,
+        Type::PTR(Box::new(Type::CHAR))
+    );
+    let f = &parser.functions()[0];
+    assert_eq!(f.ret, Type::UCHAR);
+    assert_eq!(f.params, vec![Type::CHAR, Type::PTR(Box::new(Type::UCHAR))]);
+    // bytes pack together, pointers stay aligned
+    assert_eq!(parser.local[0][0].offset, 1);
+    assert_eq!(parser.local[0][1].offset, 16);
+    assert_eq!(parser.local[0][2].offset, 19);
+    assert_eq!(parser.local[0][3].offset, 20);
+    if let NodeKind::Def { body, .. } = functions[0].kind.clone() {
+        if let NodeKind::Block(stmts) = body.kind {
+            assert_eq!(stmts[1].rhs.clone().unwrap().kind, NodeKind::NUM(120));
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+}
+
+#[test]
+fn string_test() {
+    let code = String::from(
+        "char *g = \"a\" \"b\"; char h[] = \"xyz\"; int main(){char s[] = \"hi\", t[8] = \"\"; return puts(\"\\tx\");}",
+    );
+    let mut parser = Parser::load(code);
+    let functions = parser.run().unwrap();
+    assert_eq!(
+        parser.strings(),
+        &[
+            b"ab".to_vec(),
+            b"xyz".to_vec(),
+            b"hi".to_vec(),
+            Vec::new(),
+            b"\tx".to_vec()
+        ]
+    );
+    assert_eq!(parser.globals()[0].init, Some(Init::Str(0)));
+    assert_eq!(
+        parser.globals()[1].val_type,
+        Type::ARRAY(Box::new(Type::CHAR), 4)
+    );
+    assert_eq!(
+        parser.local[0][0].val_type,
+        Type::ARRAY(Box::new(Type::CHAR), 3)
+    );
+    assert_eq!(parser.frame_size(0), 11);
+    if let NodeKind::Def { body, .. } = functions[0].kind.clone() {
+        if let NodeKind::Block(stmts) = body.kind {
+            if let NodeKind::Func { argv, .. } = stmts[2].rhs.clone().unwrap().kind {
+                assert_eq!(argv[0].kind, NodeKind::Str(4));
+                assert_eq!(argv[0].ty, Some(Type::ARRAY(Box::new(Type::CHAR), 3)));
+            } else {
+                panic!("expected call");
+            }
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+}
+
+#[test]
+fn integer_test() {
+    let code = String::from(
+        "short s; unsigned u; long int l; long long unsigned ll; int f(short int a, unsigned long b){signed short c; char d; int e; return 0;}",
+    );
+    let mut parser = Parser::load(code);
+    parser.run().unwrap();
+    let types: Vec<Type> = parser
+        .globals()
+        .iter()
+        .map(|global| global.val_type.clone())
+        .collect();
+    assert_eq!(
+        types,
+        vec![Type::SHORT, Type::UINT, Type::LONG, Type::ULONG]
+    );
+    assert_eq!(parser.functions()[0].params, vec![Type::SHORT, Type::ULONG]);
+    // each local on its own alignment
+    let offsets: Vec<usize> = parser.local[0].iter().map(|local| local.offset).collect();
+    assert_eq!(offsets, vec![2, 16, 18, 19, 24]);
+    assert_eq!(Type::USHORT.to_string(), "unsigned short");
+    assert_eq!(Type::CHAR.common(&Type::USHORT), Type::INT);
+    assert_eq!(Type::INT.common(&Type::UINT), Type::UINT);
+    assert_eq!(Type::UINT.common(&Type::LONG), Type::LONG);
+
+    let code = String::from("short long a; unsigned signed b; long long long c; char int d;");
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    assert_eq!(errors.len(), 4);
+    for error in &errors {
+        assert_eq!(error.message, "invalid combination of type specifiers");
+    }
+    assert_eq!(errors[0].primary.span.len, 10);
+}
+
+#[test]
+fn struct_test() {
+    let code = String::from(
+        "struct point { char tag; int x; long y; } origin; struct line { struct point a, b; short id; }; struct node { int val; struct node *next; } head; int main(){struct point { int z; } p; {struct line l;} return 0;}",
+    );
+    let mut parser = Parser::load(code);
+    parser.run().unwrap();
+    let point = match &parser.globals()[0].val_type {
+        Type::STRUCT(def) => def.clone(),
+        _ => panic!("expected struct"),
+    };
+    let offsets: Vec<usize> = point
+        .layout()
+        .unwrap()
+        .members
+        .iter()
+        .map(|member| member.offset)
+        .collect();
+    assert_eq!(offsets, vec![0, 4, 8]);
+    assert_eq!(Type::STRUCT(point.clone()).size(), 16);
+    assert_eq!(Type::STRUCT(point.clone()).align(), 8);
+    // the member points back to the struct it is in
+    let node = &parser.globals()[1].val_type;
+    match node {
+        Type::STRUCT(def) => {
+            let next = def.member("next").unwrap();
+            assert_eq!(next.offset, 8);
+            assert_eq!(next.ty, Type::PTR(Box::new(node.clone())));
+        }
+        _ => panic!("expected struct"),
+    }
+    assert_eq!(node.to_string(), "struct node");
+    // the inner `struct point` shadows the outer one; the block's
+    // `struct line` still holds the outer one
+    let inner = &parser.local[0][0].val_type;
+    assert_eq!(inner.size(), 4);
+    assert_ne!(*inner, Type::STRUCT(point));
+    assert_eq!(parser.local[0][1].val_type.size(), 40);
+    assert_eq!(parser.local[0][1].offset, 48);
+}
+
+#[test]
+fn struct_error_test() {
+    let code = String::from(
+        "struct s { int a; char a; }; struct t { int x; }; struct t { int y; }; struct u v; struct w { struct w self; }; struct t f(){} int g(struct t p){}",
+    );
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "duplicate member `a`",
+            "redefinition of `struct t`",
+            "variable `v` has incomplete type `struct u`",
+            "member `self` has incomplete type `struct w`",
+            "returning a struct by value is not supported",
+            "passing a struct by value is not supported",
+        ]
+    );
+}
+
+#[test]
+fn union_test() {
+    let code = String::from(
+        "union value { char c; long l; int a[3]; } v; struct s { char tag; union { short s; char b[3]; } u; } w;",
+    );
+    let mut parser = Parser::load(code);
+    parser.run().unwrap();
+    let value = &parser.globals()[0].val_type;
+    assert_eq!(value.size(), 16);
+    assert_eq!(value.align(), 8);
+    assert_eq!(value.to_string(), "union value");
+    match value {
+        Type::STRUCT(def) => assert!(def
+            .layout()
+            .unwrap()
+            .members
+            .iter()
+            .all(|member| member.offset == 0)),
+        _ => panic!("expected union"),
+    }
+    let outer = &parser.globals()[1].val_type;
+    assert_eq!(outer.size(), 6);
+    match outer {
+        Type::STRUCT(def) => assert_eq!(def.member("u").unwrap().offset, 2),
+        _ => panic!("expected struct"),
+    }
+}
+
+#[test]
+fn enum_test() {
+    let code = String::from(
+        "enum color { RED, GREEN = 5, BLUE } c; int a[BLUE]; int g = GREEN * 2; int main(){enum color d = RED; {int GREEN; GREEN = 1;} return GREEN;}",
+    );
+    let mut parser = Parser::load(code);
+    let functions = parser.run().unwrap();
+    assert_eq!(parser.globals()[0].val_type, Type::INT);
+    assert_eq!(
+        parser.globals()[1].val_type,
+        Type::ARRAY(Box::new(Type::INT), 6)
+    );
+    assert_eq!(parser.globals()[2].init, Some(Init::Num(10)));
+    if let NodeKind::Def { body, .. } = functions[0].kind.clone() {
+        if let NodeKind::Block(stmts) = body.kind {
+            assert_eq!(stmts[0].rhs.clone().unwrap().kind, NodeKind::NUM(0));
+            // the local hides the enumerator, but only in its block
+            if let NodeKind::Block(inner) = stmts[1].kind.clone() {
+                let assign = inner[1].clone();
+                assert!(matches!(assign.lhs.unwrap().kind, NodeKind::LVAL(_)));
+            } else {
+                panic!("expected block");
+            }
+            assert_eq!(stmts[2].rhs.clone().unwrap().kind, NodeKind::NUM(5));
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+}
+
+#[test]
+fn tag_error_test() {
+    let code = String::from(
+        "enum e { A, B, A }; int B; struct s { int x; }; union s u; enum f x; enum g { C = 3000000000 }; enum e { D }; enum h { E = B + x };",
+    );
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "redefinition of `A`",
+            "redefinition of `B`",
+            "use of `s` with a tag type that does not match its declaration",
+            "`enum f` is not defined",
+            "enumerator value 3000000000 does not fit in `int`",
+            "redefinition of `enum e`",
+            "variable `x` not defined",
+        ]
+    );
+}
+
+#[test]
+fn switch_test() {
+    let code = String::from(
+        "int main(int x){switch (x) { case 1: case 2 + 1: x = 0; break; default: while (x) break; } return x;}",
+    );
+    let mut parser = Parser::load(code);
+    let functions = parser.run().unwrap();
+    if let NodeKind::Def { body, .. } = functions[0].kind.clone() {
+        if let NodeKind::Block(stmts) = body.kind {
+            match &stmts[0].kind {
+                NodeKind::Switch { cases, default } => {
+                    let values: Vec<i64> = cases.iter().map(|(value, _)| *value).collect();
+                    assert_eq!(values, vec![1, 3]);
+                    assert!(*default);
+                }
+                _ => panic!("expected switch"),
+            }
+            if let NodeKind::Block(items) = stmts[0].rhs.clone().unwrap().kind {
+                assert_eq!(items[0].kind, NodeKind::Case(0));
+                // `case 1:` labels the labelled `case 3:`
+                assert_eq!(items[0].lhs.clone().unwrap().kind, NodeKind::Case(1));
+                assert_eq!(items[1].kind, NodeKind::Break);
+                assert_eq!(items[2].kind, NodeKind::Default);
+            } else {
+                panic!("expected block");
+            }
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+
+    let code = String::from(
+        "int main(int x){case 1: x; break; switch (x) { case 1: ; case 1: ; default: ; default: ; case x: ; } return 0;}",
+    );
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "`case` label outside of a switch",
+            "`break` outside of a loop or switch",
+            "multiple default labels in one switch",
+            "case label is not a compile-time constant",
+        ]
+    );
+}
+
+#[test]
+fn typedef_test() {
+    let code = String::from(
+        "typedef int T, *P, A[3]; typedef struct s S; struct s { S *next; }; T g; P f(A a, T b){T T; T = b; {typedef char T; T c;} return 0;}",
+    );
+    let mut parser = Parser::load(code);
+    let functions = parser.run().unwrap();
+    assert_eq!(parser.globals()[0].val_type, Type::INT);
+    let f = &parser.functions()[0];
+    assert_eq!(f.ret, Type::PTR(Box::new(Type::INT)));
+    // an array parameter is still a pointer when named by a typedef
+    assert_eq!(f.params, vec![Type::PTR(Box::new(Type::INT)), Type::INT]);
+    assert_eq!(parser.local[0][2].val_type, Type::INT);
+    assert_eq!(parser.local[0][3].val_type, Type::CHAR);
+    if let NodeKind::Def { body, .. } = functions[0].kind.clone() {
+        if let NodeKind::Block(stmts) = body.kind {
+            // the variable hides the typedef, so this is an assignment
+            assert_eq!(stmts[1].kind, NodeKind::Assign);
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+}
+
+#[test]
+fn typedef_error_test() {
+    let code = String::from(
+        "typedef int T; typedef char T; int T; typedef long L; int main(){return L + 1;}",
+    );
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "typedef redefinition with different types (`char` vs `int`)",
+            "redefinition of `T`",
+            "unexpected type name `L`",
+        ]
+    );
+}
+
+#[test]
+fn declarator_test() {
+    let code = String::from(
+        "int *a[4]; int (*p)[4]; int (*fp)(int, char *); int (*(*g)(int))[2]; typedef int F(int); F *h; int (*f(int x, int m[][3]))(long){return 0;}",
+    );
+    let mut parser = Parser::load(code);
+    parser.run().unwrap();
+    let int = || Box::new(Type::INT);
+    let types: Vec<String> = parser
+        .globals()
+        .iter()
+        .map(|global| global.val_type.to_string())
+        .collect();
+    assert_eq!(
+        types,
+        vec![
+            "int *[4]",
+            "int (*)[4]",
+            "int (*)(int, char *)",
+            "int (*(*)(int))[2]",
+            "int (*)(int)",
+        ]
+    );
+    assert_eq!(
+        parser.globals()[0].val_type,
+        Type::ARRAY(Box::new(Type::PTR(int())), 4)
+    );
+    assert_eq!(
+        parser.globals()[1].val_type,
+        Type::PTR(Box::new(Type::ARRAY(int(), 4)))
+    );
+    // a function returning a function pointer takes its own parameters
+    let f = &parser.functions()[0];
+    assert_eq!(
+        f.ret,
+        Type::PTR(Box::new(Type::FUNC(int(), vec![Type::LONG], false)))
+    );
+    assert_eq!(
+        f.params,
+        vec![Type::INT, Type::PTR(Box::new(Type::ARRAY(int(), 3)))]
+    );
+}
+
+#[test]
+fn cast_test() {
+    let code = String::from("{long l; int *p; p = (int *)l; (char)l + 1;}");
+    let mut parser = Parser::load(code);
+    parser.init();
+    let node = parser.parse_stmt().unwrap();
+    if let NodeKind::Block(stmts) = node.kind {
+        let cast = stmts[2].rhs.clone().unwrap();
+        assert_eq!(cast.kind, NodeKind::Cast);
+        assert_eq!(cast.ty, Some(Type::PTR(Box::new(Type::INT))));
+        // the cast binds tighter than the addition
+        let add = stmts[3].clone();
+        assert_eq!(add.kind, NodeKind::ADD);
+        assert_eq!(add.lhs.unwrap().ty, Some(Type::CHAR));
+    } else {
+        panic!("expected block");
+    }
+}
+
+#[test]
+fn declarator_error_test() {
+    let code = String::from(
+        "int f(int){return 0;} int g()[3]; int h[2](int); int a[][2]; struct s { int m(int); }; int main(){return (int x)1;}",
+    );
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "parameter name omitted",
+            "functions cannot return arrays",
+            "array of functions of type `int (int)`",
+            "array size missing",
+            "member `m` has function type `int (int)`",
+            "unexpected identifier `x` in type name",
+        ]
+    );
+}
+
+#[test]
+fn function_pointer_test() {
+    let code = String::from(
+        "int add(int a, int b){return a + b;} int main(){int (*fp)(int, int); fp = add; fp(1, 2); (*fp)(3, 4); return add(5, 6);}",
+    );
+    let mut parser = Parser::load(code);
+    let functions = parser.run().unwrap();
+    if let NodeKind::Def { body, .. } = functions[1].kind.clone() {
+        if let NodeKind::Block(stmts) = body.kind {
+            // a function named without a call is its designator
+            let designator = stmts[1].rhs.clone().unwrap();
+            assert_eq!(designator.kind, NodeKind::GVAL("add".to_string()));
+            assert_eq!(
+                designator.ty,
+                Some(Type::FUNC(
+                    Box::new(Type::INT),
+                    vec![Type::INT, Type::INT],
+                    false
+                ))
+            );
+            // a variable is called through, the function by name
+            let call = stmts[2].clone();
+            assert!(matches!(call.kind, NodeKind::Call(ref argv) if argv.len() == 2));
+            assert_eq!(call.lhs.unwrap().kind, NodeKind::LVAL(8));
+            assert_eq!(stmts[3].lhs.clone().unwrap().kind, NodeKind::Deref);
+            let ret = stmts[4].rhs.clone().unwrap();
+            assert!(matches!(ret.kind, NodeKind::Func { ref name, .. } if name == "add"));
+        } else {
+            panic!("expected function body");
+        }
+    } else {
+        panic!("expected function definition");
+    }
+}
+
+#[test]
+fn prototype_test() {
+    let code = String::from(
+        "int printf(char *fmt, ...); int odd(int); int even(int n){int g(long); return odd(n);} int odd(int n){return even(n);}",
+    );
+    let mut parser = Parser::load(code);
+    parser.run().unwrap();
+    let functions = parser.functions();
+    let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["printf", "odd", "even", "g"]);
+    let printf = &functions[0];
+    assert!(printf.variadic && !printf.defined);
+    assert_eq!(printf.ty().to_string(), "int (char *, ...)");
+    // the definition completes the declaration
+    assert!(functions[1].defined);
+    assert_eq!(functions[3].params, vec![Type::LONG]);
+    // `(void)` takes no arguments; `()` says nothing until a prototype does
+    let code = String::from(
+        "int f(void); int g(); int h(); int (*p)(void); int g(int a, int b){return a + b;} \
+         int f(void){return g(1, 2) + h(3);} int k(){return 0;}",
+    );
+    let mut parser = Parser::load(code);
+    parser.run().unwrap();
+    let functions = parser.functions();
+    assert_eq!(functions[0].ty().to_string(), "int (void)");
+    assert_eq!(functions[1].ty().to_string(), "int (int, int)");
+    assert!(functions[1].defined);
+    assert!(functions[2].ty().is_unprototyped());
+    assert_eq!(functions[2].ty().to_string(), "int ()");
+    assert_eq!(functions[3].ty().to_string(), "int (void)");
+    assert_eq!(
+        parser.globals()[0].val_type,
+        Type::PTR(Box::new(Type::FUNC(Box::new(Type::INT), vec![], false)))
+    );
+}
 
-    
-        
+#[test]
+fn prototype_error_test() {
+    let code = String::from(
+        "int f(int); int f(char *p){return 0;} int g(...); int x; int x(int); int k(int a){return a;} int k(int a){return a;} \
+         int m(void); int m(int a); int n(); long n(int a); int v(void *p);",
+    );
+    let mut parser = Parser::load(code);
+    let errors = parser.run().unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "conflicting types for `f`",
+            "`...` needs a named parameter before it",
+            "redefinition of `x`",
+            "redefinition of function `k`",
+            "conflicting types for `m`",
+            "conflicting types for `n`",
+            "expected close parenthesis",
+        ]
+    );
 }
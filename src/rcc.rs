@@ -1,25 +1,175 @@
-use crate::parser::{Node, NodeKind, Parser};
-use std::env::args;
+use crate::diagnostic::{Diagnostic, E_CODEGEN, E_IO};
+use crate::parser::{align_to, Init, Node, NodeKind, Parser, Type};
+use crate::sema;
+use crate::span::Span;
+use crate::target::Target;
+use std::io::{self, Write};
 
 pub struct Rcc {
     parser: Parser,
-    mangle: u8,
+    mangle: usize,
+    breaks: Vec<String>,   // where `break` jumps, innermost last
+    switches: Vec<String>, // prefix of the case labels of each open switch
+    out: Box<dyn Write>,   // where the assembly goes
+    target: Target,
+}
+
+// write one line of assembly to the output
+macro_rules! emit {
+    ($rcc:expr, $($arg:tt)*) => {
+        writeln!($rcc.out, $($arg)*).map_err(Rcc::io_error)?
+    };
 }
 
 // C ABI register
 const ARG_REGISTER: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+// their low dwords, words and bytes
+const ARG_REGISTER32: [&str; 6] = ["edi", "esi", "edx", "ecx", "r8d", "r9d"];
+const ARG_REGISTER16: [&str; 6] = ["di", "si", "dx", "cx", "r8w", "r9w"];
+const ARG_REGISTER8: [&str; 6] = ["dil", "sil", "dl", "cl", "r8b", "r9b"];
 
 impl Rcc {
-    pub fn init(src: String) -> Self {
-        let parser = Parser::load(src);
-        Self { parser, mangle: 1 }
+    pub fn init(src: String, file: usize, out: Box<dyn Write>, target: Target) -> Self {
+        let parser = Parser::with_file(src, file);
+        Self {
+            parser,
+            mangle: 1,
+            breaks: Vec::new(),
+            switches: Vec::new(),
+            out,
+            target,
+        }
+    }
+
+    fn error(message: &str, span: Span) -> Diagnostic {
+        Diagnostic::error(E_CODEGEN, message, span)
+    }
+
+    fn io_error(err: io::Error) -> Diagnostic {
+        let message = format!("cannot write assembly: {}", err);
+        Diagnostic::error(E_IO, &message, Span::default())
     }
 
     // push the variable address into the stack
-    fn addr(offset: u8) {
-        println!("  mov rax, rbp");
-        println!("  sub rax, {}", offset);
-        println!("  push rax");
+    fn addr(&mut self, node: &Node) -> Result<(), Diagnostic> {
+        match &node.kind {
+            NodeKind::LVAL(offset) => {
+                emit!(self, "  mov rax, rbp");
+                emit!(self, "  sub rax, {}", offset);
+            }
+            // a function declared but not defined here comes from elsewhere
+            NodeKind::GVAL(name)
+                if matches!(node.ty, Some(Type::FUNC(..)))
+                    && !self
+                        .parser
+                        .functions()
+                        .iter()
+                        .any(|function| function.name == *name && function.defined) =>
+            {
+                for line in self.target.got(name) {
+                    emit!(self, "{}", line);
+                }
+            }
+            NodeKind::GVAL(name) => {
+                for line in self.target.address(name) {
+                    emit!(self, "{}", line);
+                }
+            }
+            NodeKind::Str(id) => emit!(self, "  lea rax, [rip + {}]", Self::string_label(*id)),
+            // the pointer's value is the address
+            NodeKind::Deref => {
+                self.gen(node.rhs.clone().unwrap())?;
+                return Ok(());
+            }
+            // base + index * element size
+            NodeKind::Subscript => {
+                let size = node.ty.as_ref().map_or(8, Type::size);
+                self.gen(node.lhs.clone().unwrap())?;
+                self.gen(node.rhs.clone().unwrap())?;
+                emit!(self, "  pop r10");
+                emit!(self, "  pop rax");
+                emit!(self, "  imul r10, {}", size);
+                emit!(self, "  add rax, r10");
+            }
+            // the struct's address plus the member's offset
+            NodeKind::Member(name) => {
+                let base = node.lhs.as_ref().unwrap();
+                let offset = match &base.ty {
+                    Some(Type::STRUCT(def)) => def.member(name).map_or(0, |member| member.offset),
+                    _ => return Err(Self::error("expected struct.", base.span)),
+                };
+                self.addr(base)?;
+                emit!(self, "  pop rax");
+                emit!(self, "  add rax, {}", offset);
+            }
+            _ => return Err(Self::error("expected lvalue.", node.span)),
+        }
+        emit!(self, "  push rax");
+        Ok(())
+    }
+
+    // replace the address on top of the stack with the value stored there;
+    // an array is left as its address, which is what it decays to, as is a
+    // function, and so is a struct, which is copied from there when stored
+    fn load(&mut self, ty: &Option<Type>) -> Result<(), Diagnostic> {
+        emit!(self, "  pop rax");
+        match ty {
+            Some(Type::ARRAY(..) | Type::STRUCT(_) | Type::FUNC(..)) => {}
+            Some(Type::CHAR) => emit!(self, "  movsx rax, byte ptr [rax]"),
+            Some(Type::UCHAR) => emit!(self, "  movzx rax, byte ptr [rax]"),
+            Some(Type::SHORT) => emit!(self, "  movsx rax, word ptr [rax]"),
+            Some(Type::USHORT) => emit!(self, "  movzx rax, word ptr [rax]"),
+            Some(Type::INT) => emit!(self, "  movsxd rax, dword ptr [rax]"),
+            Some(Type::UINT) => emit!(self, "  mov eax, dword ptr [rax]"),
+            _ => emit!(self, "  mov rax, [rax]"),
+        }
+        emit!(self, "  push rax");
+        Ok(())
+    }
+
+    // pop a value and an address below it, store the value there and push
+    // it back as it reads once stored
+    fn store(&mut self, ty: &Option<Type>) -> Result<(), Diagnostic> {
+        emit!(self, "  pop r10");
+        emit!(self, "  pop rax");
+        // a struct value is its address; copy it byte by byte
+        if let Some(Type::STRUCT(_)) = ty {
+            for index in 0..ty.as_ref().map_or(0, Type::size) {
+                emit!(self, "  mov r11b, [r10+{}]", index);
+                emit!(self, "  mov [rax+{}], r11b", index);
+            }
+            emit!(self, "  push rax");
+            return Ok(());
+        }
+        match ty.as_ref().map_or(8, Type::size) {
+            1 => emit!(self, "  mov [rax], r10b"),
+            2 => emit!(self, "  mov [rax], r10w"),
+            4 => emit!(self, "  mov [rax], r10d"),
+            _ => emit!(self, "  mov [rax], r10"),
+        }
+        emit!(self, "  mov rax, r10");
+        self.extend(ty)?;
+        emit!(self, "  push rax");
+        Ok(())
+    }
+
+    // every value is kept in 64 bits, sign- or zero-extended from its type;
+    // redo that for rax after it was computed or truncated in a wider type
+    fn extend(&mut self, ty: &Option<Type>) -> Result<(), Diagnostic> {
+        match ty {
+            Some(Type::CHAR) => emit!(self, "  movsx rax, al"),
+            Some(Type::UCHAR) => emit!(self, "  movzx rax, al"),
+            Some(Type::SHORT) => emit!(self, "  movsx rax, ax"),
+            Some(Type::USHORT) => emit!(self, "  movzx rax, ax"),
+            Some(Type::INT) => emit!(self, "  movsxd rax, eax"),
+            Some(Type::UINT) => emit!(self, "  mov eax, eax"),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn string_label(id: usize) -> String {
+        format!(".L.str.{}", id)
     }
 
     // get a new name-mangling
@@ -29,50 +179,128 @@ impl Rcc {
         format!(".L{}", mangle)
     }
 
-    fn gen(&mut self, node: Box<Node>) -> Result<(), String> {
+    // call the function `name`, or else the one whose address was pushed
+    // before the arguments, leaving the result in rax
+    fn call(
+        &mut self,
+        argv: Vec<Box<Node>>,
+        name: Option<&str>,
+        variadic: bool,
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        // evaluate every argument before loading the registers, a call
+        // nested in a later argument would clobber them
+        let args = argv.len();
+        if args > ARG_REGISTER.len() {
+            return Err(Self::error(
+                "too many arguments, at most 6 are passed in registers",
+                span,
+            ));
+        }
+        for arg in argv {
+            self.gen(arg)?;
+        }
+        for register in ARG_REGISTER[..args].iter().rev() {
+            emit!(self, "  pop {}", register);
+        }
+        // not in rax: a variadic callee reads al as the number of vector
+        // registers holding arguments, which is none
+        let call = match name {
+            Some(name) => self.target.call(name),
+            None => {
+                emit!(self, "  pop r10");
+                vec!["  call r10".to_string()]
+            }
+        };
+        if variadic {
+            emit!(self, "  mov eax, 0");
+        }
+
+        // Sys-V wants rsp 16-byte aligned at the call; the frame is, but
+        // the temporaries pushed on top of it may not be
+        let unaligned = self.pop_mangle();
+        let end = self.pop_mangle();
+        emit!(self, "  test rsp, 15");
+        emit!(self, "  jnz {}", unaligned);
+        for line in &call {
+            emit!(self, "{}", line);
+        }
+        emit!(self, "  jmp {}", end);
+        emit!(self, "{}:", unaligned);
+        emit!(self, "  sub rsp, 8");
+        for line in &call {
+            emit!(self, "{}", line);
+        }
+        emit!(self, "  add rsp, 8");
+        emit!(self, "{}:", end);
+        Ok(())
+    }
+
+    // statements leave the stack as they found it; expressions used as
+    // statements push a value that is dropped here
+    fn gen_stmt(&mut self, node: Box<Node>) -> Result<(), Diagnostic> {
+        match node.kind {
+            NodeKind::Block(_)
+            | NodeKind::If(_)
+            | NodeKind::While
+            | NodeKind::For { .. }
+            | NodeKind::Nop
+            | NodeKind::Switch { .. }
+            | NodeKind::Case(_)
+            | NodeKind::Default
+            | NodeKind::Break
+            | NodeKind::Return => self.gen(node),
+            _ => {
+                self.gen(node)?;
+                emit!(self, "  pop rax");
+                Ok(())
+            }
+        }
+    }
+
+    fn gen(&mut self, node: Box<Node>) -> Result<(), Diagnostic> {
         if let NodeKind::Declar = node.kind {
             let lhs = node.lhs.unwrap();
-            if let NodeKind::LVAL(offset) = lhs.kind {
-                Self::addr(offset);
-                println!("  push 0");
-                println!("  pop r10");
-                println!("  pop rax");
-                println!("  mov [rax], r10");
-                println!("  push r10");
+            self.addr(&lhs)?;
+            // `char s[N] = "..."`: copy the string and zero the rest
+            if let (Some(Type::ARRAY(_, len)), Some(NodeKind::Str(id))) =
+                (&lhs.ty, node.rhs.as_ref().map(|rhs| &rhs.kind))
+            {
+                let bytes = self.parser.strings()[*id].clone();
+                emit!(self, "  mov rax, [rsp]");
+                for index in 0..*len {
+                    let byte = bytes.get(index).copied().unwrap_or(0);
+                    emit!(self, "  mov byte ptr [rax+{}], {}", index, byte);
+                }
                 return Ok(());
-            } else {
-                return Err(String::from("expected lval"));
             }
+            match (node.rhs, &lhs.ty) {
+                (Some(init), _) => self.gen(init)?,
+                // aggregates start out with whatever the stack held
+                (None, Some(Type::ARRAY(..) | Type::STRUCT(_))) => return Ok(()),
+                (None, _) => emit!(self, "  push 0"),
+            }
+            self.store(&lhs.ty)?;
+            return Ok(());
         }
-        // *lval
+        // *pointer
         if let NodeKind::Deref = node.kind {
-            if let Some(rhs) = &node.rhs {
-                if let NodeKind::LVAL(offset) = rhs.kind {
-                    Self::addr(offset);
-                    println!("  pop rax");
-                    println!("  mov rax, [rax]");
-                    println!("  mov rax, [rax]");
-                    println!("  push rax");
-                    return Ok(());
-                } else {
-                    return Err("expected lvalue.".to_string());
-                }
+            if let Some(rhs) = node.rhs {
+                self.gen(rhs)?;
+                self.load(&node.ty)?;
+                return Ok(());
             } else {
-                return Err("expected expression.".to_string());
+                return Err(Self::error("expected expression.", node.span));
             }
         }
 
         // &lval
         if let NodeKind::Addr = node.kind {
             if let Some(rhs) = &node.rhs {
-                if let NodeKind::LVAL(offset) = rhs.kind {
-                    Self::addr(offset);
-                    return Ok(());
-                } else {
-                    return Err("expected lvalue.".to_string());
-                }
+                self.addr(rhs)?;
+                return Ok(());
             } else {
-                return Err("expected expression.".to_string());
+                return Err(Self::error("expected expression.", node.span));
             }
         }
 
@@ -83,62 +311,95 @@ impl Rcc {
             local,
         } = node.kind
         {
-            println!("_{}:", name);
-            let offsets = self.parser.get_local_size(local);
-            self.prolog(offsets, args);
+            for line in self.target.function_header(&name) {
+                emit!(self, "{}", line);
+            }
+            emit!(self, "{}:", self.target.symbol(&name));
+            let frame = self.parser.frame_size(local);
+            let params = self.parser.params(local, args);
+            self.prolog(frame, params)?;
             if let NodeKind::Block(stmts) = body.kind {
                 for stmt in stmts {
-                    self.gen(stmt)?;
-                    println!("  pop rax");
+                    self.gen_stmt(stmt)?;
                 }
             } else {
-                return Err("expected function body".to_string());
+                return Err(Self::error("expected function body", body.span));
+            }
+            self.epilog()?;
+            for line in self.target.function_footer(&name) {
+                emit!(self, "{}", line);
             }
-            Rcc::epilog();
             return Ok(());
         }
 
         if let NodeKind::Nop = node.kind {
-            println!("  nop");
+            emit!(self, "  nop");
+            return Ok(());
+        }
+
+        if let NodeKind::Cast = node.kind {
+            self.gen(node.rhs.unwrap())?;
+            emit!(self, "  pop rax");
+            self.extend(&node.ty)?;
+            emit!(self, "  push rax");
             return Ok(());
         }
 
         if let NodeKind::Func { name, argv } = node.kind {
-            for (index, arg) in argv.into_iter().enumerate() {
-                self.gen(arg)?;
-                println!("  pop rax");
-                println!("  mov {}, rax", ARG_REGISTER[index]);
-            }
+            // a function never declared may be variadic, like `printf`
+            let variadic = self
+                .parser
+                .functions()
+                .iter()
+                .find(|function| function.name == name)
+                .is_none_or(|function| function.variadic);
+            self.call(argv, Some(&name), variadic, node.span)?;
+            // only the bits of the return type are defined
+            self.extend(&node.ty)?;
+            emit!(self, "  push rax");
+            return Ok(());
+        }
 
-            println!("  sub rsp, 8"); // Sys-V
-            println!("  call _{}", name);
-            println!("  add rsp, 8"); // Sys-V
-            println!("  push rax");
+        if let NodeKind::Call(argv) = node.kind {
+            let callee = node.lhs.unwrap();
+            let variadic = match &callee.ty {
+                Some(Type::FUNC(_, _, variadic)) => *variadic,
+                Some(Type::PTR(base)) => matches!(**base, Type::FUNC(_, _, true)),
+                _ => false,
+            };
+            self.gen(callee)?;
+            self.call(argv, None, variadic, node.span)?;
+            self.extend(&node.ty)?;
+            emit!(self, "  push rax");
             return Ok(());
         }
 
         if let NodeKind::Block(stmts) = node.kind {
             for stmt in stmts {
-                self.gen(stmt)?;
-                println!("  pop rax");
+                self.gen_stmt(stmt)?;
             }
             return Ok(());
         }
 
         if let NodeKind::For { init, end, inc } = node.kind {
             let stmt = node.lhs.unwrap();
-            self.gen(init)?;
+            self.gen_stmt(init)?;
             let condition_mangle = self.pop_mangle();
             let end_mangle = self.pop_mangle();
-            println!("{}:", condition_mangle);
-            self.gen(end)?;
-            println!("  pop rax");
-            println!("  cmp rax, 0"); // if A = 0
-            println!("  je {}", end_mangle);
-            self.gen(stmt)?;
-            self.gen(inc)?;
-            println!("  jmp {}", condition_mangle);
-            println!("{}:", end_mangle);
+            emit!(self, "{}:", condition_mangle);
+            // an empty condition loops forever
+            if end.kind != NodeKind::Nop {
+                self.gen(end)?;
+                emit!(self, "  pop rax");
+                emit!(self, "  cmp rax, 0"); // if A = 0
+                emit!(self, "  je {}", end_mangle);
+            }
+            self.breaks.push(end_mangle.clone());
+            self.gen_stmt(stmt)?;
+            self.breaks.pop();
+            self.gen_stmt(inc)?;
+            emit!(self, "  jmp {}", condition_mangle);
+            emit!(self, "{}:", end_mangle);
             return Ok(());
         }
 
@@ -146,78 +407,129 @@ impl Rcc {
             let condition = node.lhs.unwrap();
             let stmt = node.rhs.unwrap();
             let condtion_mangle = self.pop_mangle();
-            println!("{}:", condtion_mangle);
+            emit!(self, "{}:", condtion_mangle);
             self.gen(condition)?;
-            println!("  pop rax");
-            println!("  cmp rax, 0"); // if A = 0
+            emit!(self, "  pop rax");
+            emit!(self, "  cmp rax, 0"); // if A = 0
             let end_mangle = self.pop_mangle();
-            println!("  je {}", end_mangle);
-            self.gen(stmt)?;
-            println!("  jmp {}", condtion_mangle);
-            println!("{}:", end_mangle);
+            emit!(self, "  je {}", end_mangle);
+            self.breaks.push(end_mangle.clone());
+            self.gen_stmt(stmt)?;
+            self.breaks.pop();
+            emit!(self, "  jmp {}", condtion_mangle);
+            emit!(self, "{}:", end_mangle);
+            return Ok(());
+        }
+
+        // compare against each case in turn, then fall back to the default
+        if let NodeKind::Switch { cases, default } = node.kind {
+            self.gen(node.lhs.unwrap())?;
+            emit!(self, "  pop rax");
+            let labels = self.pop_mangle();
+            let end_mangle = self.pop_mangle();
+            for (id, (value, _)) in cases.iter().enumerate() {
+                if i32::try_from(*value).is_ok() {
+                    emit!(self, "  cmp rax, {}", value);
+                } else {
+                    emit!(self, "  movabs r10, {}", value);
+                    emit!(self, "  cmp rax, r10");
+                }
+                emit!(self, "  je {}.{}", labels, id);
+            }
+            if default {
+                emit!(self, "  jmp {}.default", labels);
+            } else {
+                emit!(self, "  jmp {}", end_mangle);
+            }
+            self.switches.push(labels);
+            self.breaks.push(end_mangle.clone());
+            self.gen_stmt(node.rhs.unwrap())?;
+            self.breaks.pop();
+            self.switches.pop();
+            emit!(self, "{}:", end_mangle);
+            return Ok(());
+        }
+
+        if let NodeKind::Case(id) = node.kind {
+            emit!(self, "{}.{}:", self.switches.last().unwrap(), id);
+            self.gen_stmt(node.lhs.unwrap())?;
+            return Ok(());
+        }
+
+        if let NodeKind::Default = node.kind {
+            emit!(self, "{}.default:", self.switches.last().unwrap());
+            self.gen_stmt(node.lhs.unwrap())?;
+            return Ok(());
+        }
+
+        if let NodeKind::Break = node.kind {
+            emit!(self, "  jmp {}", self.breaks.last().unwrap());
             return Ok(());
         }
 
         if let NodeKind::If(condition) = node.kind {
             self.gen(condition)?;
-            println!("  pop rax");
-            println!("  cmp rax, 0"); // if A = 0
+            emit!(self, "  pop rax");
+            emit!(self, "  cmp rax, 0"); // if A = 0
             match node.rhs {
                 // if-else
                 Some(rhs) => {
                     let else_mangle = self.pop_mangle();
-                    println!("  je {}", else_mangle);
-                    self.gen(node.lhs.unwrap())?;
+                    emit!(self, "  je {}", else_mangle);
+                    self.gen_stmt(node.lhs.unwrap())?;
                     let end_mangle = self.pop_mangle();
-                    println!("  jmp {}", end_mangle);
-                    println!("{}:", else_mangle);
-                    self.gen(rhs)?;
-                    println!("{}:", end_mangle);
+                    emit!(self, "  jmp {}", end_mangle);
+                    emit!(self, "{}:", else_mangle);
+                    self.gen_stmt(rhs)?;
+                    emit!(self, "{}:", end_mangle);
                     return Ok(());
                 }
                 // if
                 None => {
                     let end_mangle = self.pop_mangle();
-                    println!("  je {}", end_mangle);
-                    self.gen(node.lhs.unwrap())?;
-                    println!("{}:", end_mangle);
+                    emit!(self, "  je {}", end_mangle);
+                    self.gen_stmt(node.lhs.unwrap())?;
+                    emit!(self, "{}:", end_mangle);
                     return Ok(());
                 }
             }
         }
         if let NodeKind::NUM(num) = node.kind {
-            println!("  push {}", num);
+            // `push` only takes a sign-extended 32-bit immediate
+            if i32::try_from(num).is_ok() {
+                emit!(self, "  push {}", num);
+            } else {
+                emit!(self, "  movabs rax, {}", num);
+                emit!(self, "  push rax");
+            }
             return Ok(());
         }
 
-        // get the value of variable
-        if let NodeKind::LVAL(offset) = node.kind {
-            Self::addr(offset);
-            println!("  pop rax");
-            println!("  mov rax, [rax]");
-            println!("  push rax");
+        // get the value of variable or element; strings stay addresses
+        if let NodeKind::LVAL(_)
+        | NodeKind::GVAL(_)
+        | NodeKind::Str(_)
+        | NodeKind::Subscript
+        | NodeKind::Member(_) = node.kind
+        {
+            self.addr(&node)?;
+            self.load(&node.ty)?;
             return Ok(());
         }
         // assign the right value to lvalue
         if let NodeKind::Assign = node.kind {
             match &node.lhs {
-                None => return Err("expected lvalue".to_string()),
-                Some(lhs) => match lhs.kind {
-                    NodeKind::LVAL(offset) => {
-                        
-                        Self::addr(offset);
-                        self.gen(node.rhs.unwrap())?;
-                        println!("  pop r10");
-                        println!("  pop rax");
-                        println!("  mov [rax], r10");
-                        println!("  push r10");
-                        return Ok(());
-                    }
-                    _ => return Err("expected lvalue".to_string()),
-                },
+                None => return Err(Self::error("expected lvalue", node.span)),
+                Some(lhs) => {
+                    self.addr(lhs)?;
+                    self.gen(node.rhs.unwrap())?;
+                    self.store(&lhs.ty)?;
+                    return Ok(());
+                }
             }
         }
 
+        let operand = node.lhs.as_ref().and_then(|lhs| lhs.ty.clone());
         if let Some(lhs) = node.lhs {
             self.gen(lhs)?;
         }
@@ -227,82 +539,228 @@ impl Rcc {
         }
 
         if let NodeKind::Return = node.kind {
-            println!("  pop rax");
-            Rcc::epilog();
+            emit!(self, "  pop rax");
+            self.epilog()?;
             return Ok(());
         }
 
-        println!("  pop r10");
-        println!("  pop rax");
+        emit!(self, "  pop r10");
+        emit!(self, "  pop rax");
+        // both operands have the same type by now; it picks the signed or
+        // unsigned instruction
+        let signed = operand.as_ref().is_some_and(Type::is_signed);
         match node.kind {
-            NodeKind::ADD => println!("  add rax, r10"),
-            NodeKind::SUB => println!("  sub rax, r10"),
-            NodeKind::MUL => println!("  imul rax, r10"),
+            NodeKind::ADD => emit!(self, "  add rax, r10"),
+            NodeKind::SUB => emit!(self, "  sub rax, r10"),
+            NodeKind::MUL => emit!(self, "  imul rax, r10"),
             NodeKind::DIV => {
-                println!("  cqo");
-                println!("  idiv r10");
+                if signed {
+                    emit!(self, "  cqo");
+                    emit!(self, "  idiv r10");
+                } else {
+                    emit!(self, "  xor edx, edx");
+                    emit!(self, "  div r10");
+                }
             }
             NodeKind::Eq => {
-                println!("  cmp rax, r10");
-                println!("  sete al");
-                println!("  movzx rax, al")
+                emit!(self, "  cmp rax, r10");
+                emit!(self, "  sete al");
+                emit!(self, "  movzx rax, al");
             }
             NodeKind::NotEq => {
-                println!("  cmp rax, r10");
-                println!("  setne al");
-                println!("  movzx rax, al")
+                emit!(self, "  cmp rax, r10");
+                emit!(self, "  setne al");
+                emit!(self, "  movzx rax, al");
             }
             NodeKind::Lt => {
-                println!("  cmp rax, r10");
-                println!("  setl al");
-                println!("  movzx rax, al")
+                emit!(self, "  cmp rax, r10");
+                emit!(self, "  {} al", if signed { "setl" } else { "setb" });
+                emit!(self, "  movzx rax, al");
             }
             NodeKind::Leq => {
-                println!("  cmp rax, r10");
-                println!("  setle al");
-                println!("  movzx rax, al")
+                emit!(self, "  cmp rax, r10");
+                emit!(self, "  {} al", if signed { "setle" } else { "setbe" });
+                emit!(self, "  movzx rax, al");
             }
-            _ => return Err("not expected node".to_string()),
+            _ => return Err(Self::error("not expected node", node.span)),
         }
-        println!("  push rax");
+        // wrap around in the width of the result
+        self.extend(&node.ty)?;
+        emit!(self, "  push rax");
+        Ok(())
+    }
+
+    fn prefix(&mut self) -> Result<(), Diagnostic> {
+        emit!(self, ".intel_syntax noprefix");
+        emit!(self, "{}", self.target.text_section());
         Ok(())
     }
 
-    fn prefix() {
-        println!(".intel_syntax noprefix");
-        println!(".globl _main");
-        //println!("_main:");
+    // the string literals, NUL-terminated
+    fn rodata(&mut self) -> Result<(), Diagnostic> {
+        let strings = self.parser.strings().to_vec();
+        if strings.is_empty() {
+            return Ok(());
+        }
+        emit!(self, "{}", self.target.rodata_section());
+        for (id, bytes) in strings.iter().enumerate() {
+            emit!(self, "{}:", Self::string_label(id));
+            emit!(self, "  .byte {}", Self::bytes(bytes, bytes.len() + 1));
+        }
+        Ok(())
+    }
+
+    // `bytes` zero-padded to `size`, as operands of `.byte`
+    fn bytes(bytes: &[u8], size: usize) -> String {
+        (0..size)
+            .map(|index| bytes.get(index).copied().unwrap_or(0).to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // storage for the file-scope variables
+    fn data(&mut self) -> Result<(), Diagnostic> {
+        for global in self.parser.globals().to_vec() {
+            let section = match global.init {
+                Some(_) => self.target.data_section(),
+                None => self.target.bss_section(),
+            };
+            emit!(self, "{}", section);
+            for line in self.target.object_header(&global.name) {
+                emit!(self, "{}", line);
+            }
+            let size = global.val_type.size();
+            emit!(self, ".balign {}", global.val_type.align());
+            emit!(self, "{}:", self.target.symbol(&global.name));
+            match &global.init {
+                None => emit!(self, "  .zero {}", size),
+                Some(Init::Num(num)) => match size {
+                    1 => emit!(self, "  .byte {}", *num as u8),
+                    2 => emit!(self, "  .short {}", *num as u16),
+                    4 => emit!(self, "  .long {}", *num as u32),
+                    _ => emit!(self, "  .quad {}", num),
+                },
+                Some(Init::Addr { name, offset, .. }) => {
+                    for line in self.target.quad(name, *offset) {
+                        emit!(self, "{}", line);
+                    }
+                }
+                Some(Init::Str(id)) => match global.val_type {
+                    Type::ARRAY(..) => {
+                        let bytes = self.parser.strings()[*id].clone();
+                        emit!(self, "  .byte {}", Self::bytes(&bytes, size));
+                    }
+                    _ => emit!(self, "  .quad {}", Self::string_label(*id)),
+                },
+            }
+            for line in self.target.object_footer(&global.name, size) {
+                emit!(self, "{}", line);
+            }
+        }
+        Ok(())
+    }
+
+    fn suffix(&mut self) -> Result<(), Diagnostic> {
+        for line in self.target.file_footer() {
+            emit!(self, "{}", line);
+        }
+        Ok(())
     }
 
     // rbp : base pointer
     // rsp : stack pointer
-    fn prolog(&self, offsets: usize, args: usize) {
-        println!("  push rbp");
-        println!("  mov rbp, rsp");
-        println!("  sub rsp, {}", offsets * 8);
-        for arg in 0..args {
-            println!("  mov [rbp-{}], {}", (arg + 1) * 8, ARG_REGISTER[arg]);
+    // `frame` bytes of locals, rounded so rsp stays 16-byte aligned; the
+    // first six parameters come in registers, the rest in 8-byte slots the
+    // caller pushed above the return address
+    fn prolog(&mut self, frame: usize, params: Vec<(usize, Type)>) -> Result<(), Diagnostic> {
+        emit!(self, "  push rbp");
+        emit!(self, "  mov rbp, rsp");
+        emit!(self, "  sub rsp, {}", align_to(frame, 16));
+        for (index, (offset, ty)) in params.iter().enumerate() {
+            let (register, rax) = match ty.size() {
+                1 => (ARG_REGISTER8.get(index), "al"),
+                2 => (ARG_REGISTER16.get(index), "ax"),
+                4 => (ARG_REGISTER32.get(index), "eax"),
+                _ => (ARG_REGISTER.get(index), "rax"),
+            };
+            let register = match register {
+                Some(register) => register,
+                None => {
+                    let slot = 16 + 8 * (index - ARG_REGISTER.len());
+                    emit!(self, "  mov rax, [rbp+{}]", slot);
+                    rax
+                }
+            };
+            emit!(self, "  mov [rbp-{}], {}", offset, register);
         }
+        Ok(())
     }
 
-    fn epilog() {
-        println!("  leave");
-        println!("  ret");
+    fn epilog(&mut self) -> Result<(), Diagnostic> {
+        emit!(self, "  leave");
+        emit!(self, "  ret");
+        Ok(())
     }
 
-    pub fn run() -> Result<(), String> {
-        let src = args().nth(1).expect("Wrong argument number");
-        let mut rcc = Rcc::init(src);
-        let program = rcc.parser.run()?;
+    pub fn compile(&mut self) -> Result<(), Vec<Diagnostic>> {
+        let mut program = self.parser.run()?;
+        sema::run(&mut program, &self.parser)?;
 
-        Rcc::prefix();
+        self.prefix().map_err(|err| vec![err])?;
         //Rcc::prolog();
 
         for func in program {
-            rcc.gen(func)?;
+            self.gen(func).map_err(|err| vec![err])?;
         }
 
         //Rcc::epilog();
-        Ok(())
+        self.rodata().map_err(|err| vec![err])?;
+        self.data().map_err(|err| vec![err])?;
+        self.suffix().map_err(|err| vec![err])?;
+        self.out.flush().map_err(|err| vec![Rcc::io_error(err)])
     }
 }
+
+//------------------------------------------------------------------------
+
+// the assembly for `src`, for the ELF target
+#[cfg(test)]
+fn compile(src: &str) -> String {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // a writer that can still be read once `Rcc` is done with it
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let buffer = Buffer::default();
+    let mut rcc = Rcc::init(
+        src.to_string(),
+        0,
+        Box::new(buffer.clone()),
+        Target::LinuxGnu,
+    );
+    rcc.compile().unwrap();
+    let asm = buffer.0.take();
+    String::from_utf8(asm).unwrap()
+}
+
+#[test]
+fn stack_param_test() {
+    let asm =
+        compile("int f(int a, int b, int c, int d, int e, int f, char g, long h){return g + h;}");
+    assert!(asm.contains("  mov [rbp-24], r9d\n"));
+    // the seventh and eighth come from the caller's frame
+    assert!(asm.contains("  mov rax, [rbp+16]\n  mov [rbp-25], al\n"));
+    assert!(asm.contains("  mov rax, [rbp+24]\n  mov [rbp-40], rax\n"));
+}
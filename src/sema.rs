@@ -0,0 +1,1132 @@
+use crate::diagnostic::{Diagnostic, E_TYPE};
+use crate::parser::{Function, GVal, Init, Node, NodeKind, Parser, Type};
+use crate::span::Span;
+
+// Semantic analysis between parsing and codegen: works out the type of
+// every expression, bottom-up, so codegen knows what each value is, and
+// checks that values are only stored, passed and returned where their
+// type fits.
+struct Sema<'a> {
+    functions: &'a [Function], // signatures of the functions in the file
+    ret: Type,                 // return type of the function being checked
+}
+
+// check the globals' initializers and every function, reporting the first
+// error in each function
+pub(crate) fn run(program: &mut [Box<Node>], parser: &Parser) -> Result<(), Vec<Diagnostic>> {
+    let mut errors: Vec<Diagnostic> = parser
+        .globals()
+        .iter()
+        .filter_map(|global| check_global(global, parser).err())
+        .collect();
+    let mut sema = Sema {
+        functions: parser.functions(),
+        ret: Type::INT,
+    };
+    errors.extend(
+        program
+            .iter_mut()
+            .filter_map(|function| sema.annotate(function).err()),
+    );
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn error(message: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(E_TYPE, message, span)
+}
+
+// expressions that designate an object, whose address codegen can compute
+fn is_lvalue(node: &Node) -> bool {
+    matches!(
+        node.kind,
+        NodeKind::LVAL(_)
+            | NodeKind::GVAL(_)
+            | NodeKind::Str(_)
+            | NodeKind::Deref
+            | NodeKind::Subscript
+            | NodeKind::Member(_)
+    )
+}
+
+// the type an expression has once arrays and functions decay to pointers
+fn decay(ty: &Type) -> Type {
+    match ty {
+        Type::ARRAY(elem, _) => Type::PTR(elem.clone()),
+        Type::FUNC(..) => Type::PTR(Box::new(ty.clone())),
+        _ => ty.clone(),
+    }
+}
+
+// can `value` be stored in an object of type `target`? the rules are the
+// same for assignment, initialization, arguments and return values
+fn check_store(target: &Type, value: &Node) -> Result<(), Diagnostic> {
+    // `char s[N] = "..."` copies the string; the NUL may be left out
+    if let (Type::ARRAY(elem, len), NodeKind::Str(_)) = (target, &value.kind) {
+        let chars = match &value.ty {
+            Some(Type::ARRAY(_, size)) => size - 1,
+            _ => 0,
+        };
+        if !matches!(**elem, Type::CHAR | Type::UCHAR) {
+            return Err(error(
+                &format!("cannot initialize `{}` with a string literal", target),
+                value.span,
+            ));
+        }
+        if chars > *len {
+            return Err(error(
+                &format!("initializer-string for `{}` is too long", target),
+                value.span,
+            )
+            .with_label(&format!("{} characters", chars)));
+        }
+        return Ok(());
+    }
+    let found = match &value.ty {
+        Some(ty) => decay(ty),
+        None => return Err(error("expression has no value", value.span)),
+    };
+    let message = match (target, &found) {
+        // integers convert into each other implicitly
+        (target, found) if target.is_integer() && found.is_integer() => return Ok(()),
+        (Type::PTR(_) | Type::STRUCT(_), _) if target.is_compatible(&found) => return Ok(()),
+        (Type::STRUCT(_), _) | (_, Type::STRUCT(_)) => "incompatible types",
+        // `0` is the null pointer
+        (Type::PTR(_), _) if value.kind == NodeKind::NUM(0) => return Ok(()),
+        (Type::PTR(_), Type::PTR(_)) => "incompatible pointer types",
+        (Type::PTR(_), _) => "incompatible integer to pointer conversion",
+        (Type::ARRAY(..), _) => "cannot assign to an array",
+        _ => "incompatible pointer to integer conversion",
+    };
+    Err(error(message, value.span).with_label(&format!("expected `{}`, found `{}`", target, found)))
+}
+
+// `node` converted to the integer type `ty`, through a cast node unless it
+// already has that type
+fn cast(node: Box<Node>, ty: &Type) -> Box<Node> {
+    match &node.ty {
+        Some(from) if from != ty && from.is_integer() && ty.is_integer() => {
+            let span = node.span;
+            Box::new(Node {
+                kind: NodeKind::Cast,
+                lhs: None,
+                rhs: Some(node),
+                span,
+                ty: Some(ty.clone()),
+            })
+        }
+        _ => node,
+    }
+}
+
+// bring both integer operands of a binary operator to their common type,
+// which is returned; `None` if either is not an integer
+fn convert(node: &mut Node) -> Option<Type> {
+    let lhs = node.lhs.as_ref().unwrap().ty.clone()?;
+    let rhs = node.rhs.as_ref().unwrap().ty.clone()?;
+    if !lhs.is_integer() || !rhs.is_integer() {
+        return None;
+    }
+    let common = lhs.common(&rhs);
+    node.lhs = node.lhs.take().map(|lhs| cast(lhs, &common));
+    node.rhs = node.rhs.take().map(|rhs| cast(rhs, &common));
+    Some(common)
+}
+
+// the constant `value` converted to the integer type `ty`, extended to 64
+// bits by the signedness of `ty` the way codegen keeps its values
+fn wrap(value: i64, ty: &Type) -> i64 {
+    match ty {
+        Type::CHAR => value as i8 as i64,
+        Type::UCHAR => value as u8 as i64,
+        Type::SHORT => value as i16 as i64,
+        Type::USHORT => value as u16 as i64,
+        Type::INT => value as i32 as i64,
+        Type::UINT => value as u32 as i64,
+        _ => value,
+    }
+}
+
+// decimal constants are `int` when they fit and `long` otherwise
+fn literal(num: i64) -> Type {
+    if i32::try_from(num).is_ok() {
+        Type::INT
+    } else {
+        Type::LONG
+    }
+}
+
+// a global's initializer is already folded to a constant; give it back the
+// type it had as an expression
+fn check_global(global: &GVal, parser: &Parser) -> Result<(), Diagnostic> {
+    let (kind, ty) = match &global.init {
+        None => return Ok(()),
+        Some(Init::Num(num)) => (NodeKind::NUM(*num), literal(*num)),
+        Some(Init::Addr { ty, .. }) => (NodeKind::Addr, Type::PTR(Box::new(ty.clone()))),
+        Some(Init::Str(id)) => {
+            let len = parser.strings()[*id].len() + 1;
+            (NodeKind::Str(*id), Type::ARRAY(Box::new(Type::CHAR), len))
+        }
+    };
+    let value = Node {
+        ty: Some(ty),
+        ..Node::new_leaf(kind, global.init_span)
+    };
+    check_store(&global.val_type, &value)
+}
+
+impl Sema<'_> {
+    fn annotate(&mut self, node: &mut Node) -> Result<(), Diagnostic> {
+        if let NodeKind::Def { name, .. } = &node.kind {
+            let function = self
+                .functions
+                .iter()
+                .find(|function| function.name == *name);
+            self.ret = function.map_or(Type::INT, |function| function.ret.clone());
+        }
+        if let Some(lhs) = &mut node.lhs {
+            self.annotate(lhs)?;
+        }
+        if let Some(rhs) = &mut node.rhs {
+            self.annotate(rhs)?;
+        }
+        match &mut node.kind {
+            NodeKind::Block(stmts) => {
+                for stmt in stmts {
+                    self.annotate(stmt)?;
+                }
+            }
+            NodeKind::If(condition) => self.annotate(condition)?,
+            NodeKind::For { init, end, inc } => {
+                self.annotate(init)?;
+                self.annotate(end)?;
+                self.annotate(inc)?;
+            }
+            NodeKind::Func { argv, .. } | NodeKind::Call(argv) => {
+                for arg in argv {
+                    self.annotate(arg)?;
+                }
+            }
+            NodeKind::Def { body, .. } => self.annotate(body)?,
+            _ => {}
+        }
+
+        let lhs = node.lhs.as_ref().and_then(|lhs| lhs.ty.clone());
+        let rhs = node.rhs.as_ref().and_then(|rhs| rhs.ty.clone());
+        node.ty = match node.kind {
+            // variables and strings are typed by the parser
+            NodeKind::LVAL(_) | NodeKind::GVAL(_) | NodeKind::Str(_) => return Ok(()),
+            NodeKind::NUM(num) => Some(literal(num)),
+            NodeKind::ADD
+            | NodeKind::SUB
+            | NodeKind::MUL
+            | NodeKind::DIV
+            | NodeKind::Eq
+            | NodeKind::NotEq
+            | NodeKind::Lt
+            | NodeKind::Leq
+                if matches!(lhs, Some(Type::STRUCT(_))) || matches!(rhs, Some(Type::STRUCT(_))) =>
+            {
+                return Err(binary_error("invalid operands to binary expression", node))
+            }
+            NodeKind::MUL | NodeKind::DIV => match convert(node) {
+                Some(ty) => Some(ty),
+                None => return Err(binary_error("invalid operands to binary expression", node)),
+            },
+            NodeKind::Eq | NodeKind::NotEq | NodeKind::Lt | NodeKind::Leq => {
+                if convert(node).is_none() {
+                    compare(node)?;
+                }
+                Some(Type::INT)
+            }
+            // typed by the parser when written out, and by sema for the
+            // implicit conversions; only scalars convert
+            NodeKind::Cast => {
+                let target = node.ty.as_ref().unwrap();
+                let scalar = |ty: &Type| ty.is_integer() || matches!(ty, Type::PTR(_));
+                if !scalar(target) || !rhs.as_ref().map(decay).is_some_and(|ty| scalar(&ty)) {
+                    return Err(error(
+                        &format!("cannot cast `{}` to `{}`", type_name(&rhs), target),
+                        node.span,
+                    ));
+                }
+                return Ok(());
+            }
+            NodeKind::Func { .. } => Some(self.call(node)?),
+            NodeKind::Call(_) => Some(call_pointer(node)?),
+            NodeKind::ADD | NodeKind::SUB => return arith(node),
+            NodeKind::Addr => {
+                let operand = node.rhs.as_ref().unwrap();
+                if !is_lvalue(operand) {
+                    return Err(error("cannot take the address of an rvalue", operand.span)
+                        .with_label("not a variable, element or dereference"));
+                }
+                rhs.map(|rhs| Type::PTR(Box::new(rhs)))
+            }
+            NodeKind::Deref => match rhs.as_ref().and_then(Type::base) {
+                Some(base) => Some(base.clone()),
+                None => {
+                    return Err(error(
+                        &format!("cannot dereference a value of type `{}`", type_name(&rhs)),
+                        node.span,
+                    ))
+                }
+            },
+            NodeKind::Subscript => subscript(node)?,
+            NodeKind::Member(ref name) => Some(member(node, name)?),
+            NodeKind::Assign => {
+                let target = node.lhs.as_ref().unwrap();
+                if !is_lvalue(target) {
+                    return Err(error("invalid left-hand side of assignment", target.span)
+                        .with_label("cannot assign to this expression"));
+                }
+                if let Some(Type::ARRAY(..)) = lhs {
+                    return Err(error("cannot assign to an array", target.span));
+                }
+                if let Some(Type::FUNC(..)) = lhs {
+                    return Err(error("cannot assign to a function", target.span)
+                        .with_note("assign to a function pointer instead"));
+                }
+                check_store(lhs.as_ref().unwrap(), node.rhs.as_ref().unwrap())?;
+                lhs
+            }
+            NodeKind::Declar => {
+                if let Some(init) = &node.rhs {
+                    check_store(lhs.as_ref().unwrap(), init)?;
+                }
+                None
+            }
+            NodeKind::Switch { .. } => {
+                let condition = node.lhs.as_ref().unwrap();
+                if !lhs.as_ref().is_some_and(Type::is_integer) {
+                    return Err(error("switch condition is not an integer", condition.span)
+                        .with_label(&format!("found `{}`", type_name(&lhs))));
+                }
+                let ty = lhs.unwrap().promote();
+                node.lhs = node.lhs.take().map(|condition| cast(condition, &ty));
+                if let NodeKind::Switch { cases, .. } = &mut node.kind {
+                    for index in 0..cases.len() {
+                        let (value, label) = cases[index];
+                        let value = wrap(value, &ty);
+                        cases[index].0 = value;
+                        if let Some((_, prev)) =
+                            cases[..index].iter().find(|(prev, _)| *prev == value)
+                        {
+                            let value = match ty {
+                                Type::ULONG => (value as u64).to_string(),
+                                _ => value.to_string(),
+                            };
+                            return Err(error(&format!("duplicate case value `{}`", value), label)
+                                .with_secondary(*prev, "previous case here"));
+                        }
+                    }
+                }
+                None
+            }
+            NodeKind::Return => {
+                check_store(&self.ret, node.rhs.as_ref().unwrap()).map_err(|err| {
+                    err.with_note(&format!("the function returns `{}`", self.ret))
+                })?;
+                node.rhs = node.rhs.take().map(|rhs| cast(rhs, &self.ret));
+                None
+            }
+            _ => None,
+        };
+        Ok(())
+    }
+
+    // the type a call returns, once its arguments are checked against the
+    // declaration; functions never declared are taken on trust
+    fn call(&self, node: &mut Node) -> Result<Type, Diagnostic> {
+        let span = node.span;
+        let (name, argv) = match &mut node.kind {
+            NodeKind::Func { name, argv } => (name, argv),
+            _ => unreachable!(),
+        };
+        let function = match self
+            .functions
+            .iter()
+            .find(|function| function.name == *name)
+        {
+            Some(function) => function,
+            None => return Ok(Type::INT),
+        };
+        let label = if function.defined {
+            "defined here"
+        } else {
+            "declared here"
+        };
+        check_args(&format!("`{}`", name), &function.ty(), argv, span)
+            .map_err(|err| err.with_secondary(function.span, label))
+    }
+}
+
+// a call through a pointer is checked against the type pointed to
+fn call_pointer(node: &mut Node) -> Result<Type, Diagnostic> {
+    let span = node.span;
+    let callee = node.lhs.as_ref().unwrap();
+    let function = match callee.ty.as_ref().map(decay).as_ref().and_then(Type::base) {
+        Some(function @ Type::FUNC(..)) => function.clone(),
+        _ => {
+            return Err(error(
+                &format!(
+                    "called object type `{}` is not a function or function pointer",
+                    type_name(&callee.ty)
+                ),
+                callee.span,
+            ))
+        }
+    };
+    let argv = match &mut node.kind {
+        NodeKind::Call(argv) => argv,
+        _ => unreachable!(),
+    };
+    check_args(
+        &format!("function of type `{}`", function),
+        &function,
+        argv,
+        span,
+    )
+}
+
+// the arguments of a call to a function of type `function` against its
+// parameters, each converted to its parameter's type, and the type returned;
+// arguments for a `...` only go through the integer promotions
+fn check_args(
+    callee: &str,
+    function: &Type,
+    argv: &mut [Box<Node>],
+    span: Span,
+) -> Result<Type, Diagnostic> {
+    let (ret, params, variadic) = match function {
+        Type::FUNC(ret, params, variadic) => (ret, params, *variadic),
+        _ => unreachable!(),
+    };
+    if argv.len() != params.len() && !(variadic && argv.len() > params.len()) {
+        return Err(error(
+            &format!(
+                "{} takes {}{} argument{} but {} {} supplied",
+                callee,
+                if variadic { "at least " } else { "" },
+                params.len(),
+                if params.len() == 1 { "" } else { "s" },
+                argv.len(),
+                if argv.len() == 1 { "was" } else { "were" }
+            ),
+            span,
+        ));
+    }
+    for (index, arg) in argv.iter_mut().enumerate() {
+        let param = match params.get(index) {
+            Some(param) => {
+                check_store(param, arg)?;
+                param.clone()
+            }
+            None => match &arg.ty {
+                Some(Type::STRUCT(_)) => {
+                    return Err(
+                        error("passing a struct by value is not supported", arg.span)
+                            .with_note("pass a pointer to the struct instead"),
+                    )
+                }
+                Some(ty) => ty.promote(),
+                None => return Err(error("expression has no value", arg.span)),
+            },
+        };
+        let value = std::mem::replace(arg, Box::new(Node::new_leaf(NodeKind::Nop, span)));
+        *arg = cast(value, &param);
+    }
+    Ok(*ret.clone())
+}
+
+// pointer arithmetic counts in elements: `p + n` advances `n * size` bytes
+// and `p - q` is the byte distance divided by the size
+fn arith(node: &mut Node) -> Result<(), Diagnostic> {
+    let add = node.kind == NodeKind::ADD;
+    let lhs = node.lhs.as_ref().unwrap().ty.clone();
+    let rhs = node.rhs.as_ref().unwrap().ty.clone();
+    let lhs_base = lhs.as_ref().and_then(Type::base).cloned();
+    let rhs_base = rhs.as_ref().and_then(Type::base).cloned();
+    for base in lhs_base.iter().chain(rhs_base.iter()) {
+        incomplete_pointee(
+            "arithmetic on a pointer to an incomplete type",
+            base,
+            node.span,
+        )?;
+    }
+    match (lhs_base, rhs_base) {
+        (None, None) => node.ty = Some(convert(node).unwrap_or(Type::INT)),
+        (Some(base), None) => {
+            let index = node.rhs.take().unwrap();
+            node.rhs = Some(scale(index, base.size()));
+            node.ty = lhs.as_ref().map(decay);
+        }
+        // `n + p` is `p + n`
+        (None, Some(base)) if add => {
+            std::mem::swap(&mut node.lhs, &mut node.rhs);
+            let index = node.rhs.take().unwrap();
+            node.rhs = Some(scale(index, base.size()));
+            node.ty = rhs.as_ref().map(decay);
+        }
+        (None, Some(_)) => {
+            return Err(binary_error(
+                "cannot subtract a pointer from an integer",
+                node,
+            ))
+        }
+        (Some(_), Some(_)) if add => return Err(binary_error("cannot add two pointers", node)),
+        (Some(lhs_base), Some(rhs_base)) => {
+            if lhs_base != rhs_base {
+                return Err(binary_error(
+                    &format!(
+                        "cannot subtract `{}` and `{}`",
+                        type_name(&lhs),
+                        type_name(&rhs)
+                    ),
+                    node,
+                ));
+            }
+            let span = node.span;
+            let diff = Node {
+                ty: Some(Type::LONG),
+                ..node.clone()
+            };
+            let size = Node {
+                ty: Some(Type::LONG),
+                ..Node::new_leaf(NodeKind::NUM(lhs_base.size() as i64), span)
+            };
+            *node = Node {
+                ty: Some(Type::LONG),
+                ..Node::new(NodeKind::DIV, Box::new(diff), Box::new(size))
+            };
+        }
+    }
+    Ok(())
+}
+
+// `index * size` as a `long`, typed, unless the size is one
+fn scale(index: Box<Node>, size: usize) -> Box<Node> {
+    let index = cast(index, &Type::LONG);
+    if size == 1 {
+        return index;
+    }
+    let span = index.span;
+    let size = Box::new(Node {
+        ty: Some(Type::LONG),
+        ..Node::new_leaf(NodeKind::NUM(size as i64), span)
+    });
+    Box::new(Node {
+        ty: Some(Type::LONG),
+        ..Node::new(NodeKind::MUL, index, size)
+    })
+}
+
+// pointers compare with pointers to the same type, or with the null pointer
+// `0`; integers were already brought to a common type
+fn compare(node: &Node) -> Result<(), Diagnostic> {
+    let lhs = node.lhs.as_ref().unwrap();
+    let rhs = node.rhs.as_ref().unwrap();
+    let message = match (lhs.ty.as_ref().map(decay), rhs.ty.as_ref().map(decay)) {
+        (Some(lhs), Some(rhs)) if matches!(lhs, Type::PTR(_)) && lhs.is_compatible(&rhs) => {
+            return Ok(())
+        }
+        (Some(Type::PTR(_)), _) if rhs.kind == NodeKind::NUM(0) => return Ok(()),
+        (_, Some(Type::PTR(_))) if lhs.kind == NodeKind::NUM(0) => return Ok(()),
+        (Some(Type::PTR(_)), Some(Type::PTR(_))) => "comparison of distinct pointer types",
+        (Some(Type::PTR(_)), Some(_)) | (Some(_), Some(Type::PTR(_))) => {
+            "comparison between pointer and integer"
+        }
+        _ => "invalid operands to binary expression",
+    };
+    Err(binary_error(message, node))
+}
+
+// stepping a pointer needs the size of what it points to; a function
+// pointer steps by bytes, as in GCC
+fn incomplete_pointee(message: &str, base: &Type, span: Span) -> Result<(), Diagnostic> {
+    if base.is_complete() || matches!(base, Type::FUNC(..)) {
+        return Ok(());
+    }
+    let error = error(&format!("{} `{}`", message, base), span);
+    Err(match base {
+        Type::STRUCT(def) => error.with_secondary(def.span, "declared here"),
+        _ => error,
+    })
+}
+
+// an error on a binary operator, pointing out both operand types
+fn binary_error(message: &str, node: &Node) -> Diagnostic {
+    let lhs = node.lhs.as_ref().unwrap();
+    let rhs = node.rhs.as_ref().unwrap();
+    error(message, node.span)
+        .with_secondary(lhs.span, &format!("`{}`", type_name(&lhs.ty)))
+        .with_secondary(rhs.span, &format!("`{}`", type_name(&rhs.ty)))
+}
+
+// `a[i]` is `*(a + i)`, so `i[a]` is allowed too; put the array first
+fn subscript(node: &mut Node) -> Result<Option<Type>, Diagnostic> {
+    let lhs = node.lhs.as_ref().unwrap();
+    let rhs = node.rhs.as_ref().unwrap();
+    let (lhs_base, rhs_base) = match (&lhs.ty, &rhs.ty) {
+        (Some(lhs), Some(rhs)) => (lhs.base().cloned(), rhs.base().cloned()),
+        _ => (None, None),
+    };
+    match (lhs_base, rhs_base) {
+        (Some(_), Some(_)) => Err(error("array subscript is not an integer", rhs.span)),
+        (Some(base), None) => {
+            incomplete_pointee(
+                "subscript of a pointer to an incomplete type",
+                &base,
+                node.span,
+            )?;
+            Ok(Some(base))
+        }
+        (None, Some(base)) => {
+            incomplete_pointee(
+                "subscript of a pointer to an incomplete type",
+                &base,
+                node.span,
+            )?;
+            std::mem::swap(&mut node.lhs, &mut node.rhs);
+            Ok(Some(base))
+        }
+        (None, None) => Err(error(
+            &format!(
+                "subscripted value of type `{}` is not an array or pointer",
+                type_name(&lhs.ty)
+            ),
+            lhs.span,
+        )),
+    }
+}
+
+// the type of `s.name`, once `s` is known to be a struct with that member
+fn member(node: &Node, name: &str) -> Result<Type, Diagnostic> {
+    let base = node.lhs.as_ref().unwrap();
+    let def = match &base.ty {
+        Some(Type::STRUCT(def)) => def,
+        ty => {
+            return Err(error(
+                &format!(
+                    "member reference base type `{}` is not a struct",
+                    type_name(ty)
+                ),
+                base.span,
+            ))
+        }
+    };
+    if def.layout().is_none() {
+        return Err(
+            error(&format!("incomplete definition of `{}`", def), base.span)
+                .with_secondary(def.span, "declared here"),
+        );
+    }
+    match def.member(name) {
+        Some(member) => Ok(member.ty.clone()),
+        None => Err(error(
+            &format!("no member named `{}` in `{}`", name, def),
+            node.span,
+        )),
+    }
+}
+
+fn type_name(ty: &Option<Type>) -> String {
+    match ty {
+        Some(ty) => ty.to_string(),
+        None => "void".to_string(),
+    }
+}
+
+//------------------------------------------------------------------------
+
+#[cfg(test)]
+fn check(src: &str) -> Result<Vec<Box<Node>>, Vec<Diagnostic>> {
+    let mut parser = Parser::load(src.to_string());
+    let mut program = parser.run()?;
+    run(&mut program, &parser)?;
+    Ok(program)
+}
+
+#[cfg(test)]
+fn body(function: &Node) -> Vec<Box<Node>> {
+    match &function.kind {
+        NodeKind::Def { body, .. } => match &body.kind {
+            NodeKind::Block(stmts) => stmts.clone(),
+            _ => panic!("expected function body"),
+        },
+        _ => panic!("expected function definition"),
+    }
+}
+
+#[test]
+fn subscript_test() {
+    let program = check("int main(){int a[2][3]; int *p; return 1[a][2] + p[0];}").unwrap();
+    let stmts = body(&program[0]);
+    let add = stmts[2].rhs.clone().unwrap();
+    assert_eq!(add.ty, Some(Type::INT));
+    let outer = add.lhs.unwrap();
+    assert_eq!(outer.ty, Some(Type::INT));
+    let inner = outer.lhs.unwrap();
+    assert_eq!(inner.ty, Some(Type::ARRAY(Box::new(Type::INT), 3)));
+    // the operands of `1[a]` are swapped so the array comes first
+    assert_eq!(inner.rhs.unwrap().kind, NodeKind::NUM(1));
+}
+
+#[test]
+fn decay_test() {
+    let program = check("int main(){int a[4]; int *p; p = a + 1; return *a;}").unwrap();
+    let stmts = body(&program[0]);
+    let add = stmts[2].rhs.clone().unwrap();
+    assert_eq!(add.ty, Some(Type::PTR(Box::new(Type::INT))));
+    let deref = stmts[3].rhs.clone().unwrap();
+    assert_eq!(deref.ty, Some(Type::INT));
+}
+
+#[test]
+fn sema_error_test() {
+    let errors =
+        check("int f(){int a; return a[1];} int g(){int a[2]; a = 1;} int h(){int a; return *a;}")
+            .unwrap_err();
+    assert_eq!(errors.len(), 3);
+    assert_eq!(errors[0].code, E_TYPE);
+    assert_eq!(
+        errors[0].message,
+        "subscripted value of type `int` is not an array or pointer"
+    );
+    assert_eq!(errors[1].message, "cannot assign to an array");
+    assert_eq!(
+        errors[2].message,
+        "cannot dereference a value of type `int`"
+    );
+}
+
+#[test]
+fn pointer_arith_test() {
+    let program =
+        check("int main(){int a[4]; int *p; p = a + 1; p = 2 + p; return p - a;}").unwrap();
+    let stmts = body(&program[0]);
+    let add = stmts[2].rhs.clone().unwrap();
+    assert_eq!(add.ty, Some(Type::PTR(Box::new(Type::INT))));
+    let scaled = add.rhs.unwrap();
+    assert_eq!(scaled.kind, NodeKind::MUL);
+    assert_eq!(scaled.ty, Some(Type::LONG));
+    assert_eq!(scaled.lhs.unwrap().kind, NodeKind::Cast);
+    assert_eq!(scaled.rhs.unwrap().kind, NodeKind::NUM(4));
+    // the pointer moves to the left of `2 + p`
+    let add = stmts[3].rhs.clone().unwrap();
+    assert!(matches!(add.lhs.unwrap().kind, NodeKind::LVAL(_)));
+    // a `long`, narrowed to the `int` returned
+    let ret = stmts[4].rhs.clone().unwrap();
+    assert_eq!(ret.kind, NodeKind::Cast);
+    let diff = ret.rhs.unwrap();
+    assert_eq!(diff.kind, NodeKind::DIV);
+    assert_eq!(diff.ty, Some(Type::LONG));
+    assert_eq!(diff.lhs.unwrap().kind, NodeKind::SUB);
+    assert_eq!(diff.rhs.unwrap().kind, NodeKind::NUM(4));
+}
+
+#[test]
+fn pointer_arith_error_test() {
+    let errors = check(
+        "int f(int *p, int *q){return p + q;} int g(int *p){return 1 - p;} int h(int *p, int **q){return p - q;}",
+    )
+    .unwrap_err();
+    assert_eq!(errors.len(), 3);
+    assert_eq!(errors[0].message, "cannot add two pointers");
+    assert_eq!(errors[0].secondary[0].message, "`int *`");
+    assert_eq!(
+        errors[1].message,
+        "cannot subtract a pointer from an integer"
+    );
+    assert_eq!(errors[2].message, "cannot subtract `int *` and `int **`");
+    let errors = check(
+        "struct u; int f(struct u *p, struct u *q){return p - q;} \
+         struct u *g(struct u *p){return p + 1;} int h(struct u *p){p[1]; return 0;} \
+         int k(int (*f)(int)){return f + 1 != 0;}",
+    )
+    .unwrap_err();
+    assert_eq!(errors.len(), 3);
+    assert_eq!(
+        errors[0].message,
+        "arithmetic on a pointer to an incomplete type `struct u`"
+    );
+    assert_eq!(errors[0].secondary[0].message, "declared here");
+    assert_eq!(
+        errors[1].message,
+        "arithmetic on a pointer to an incomplete type `struct u`"
+    );
+    assert_eq!(
+        errors[2].message,
+        "subscript of a pointer to an incomplete type `struct u`"
+    );
+}
+
+#[test]
+fn lvalue_error_test() {
+    let errors =
+        check("int f(int a){a + 1 = 2; return 0;} int g(int a){return &(a + 1);}").unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].message, "invalid left-hand side of assignment");
+    assert_eq!(errors[0].primary.span.column, 14);
+    assert_eq!(errors[1].message, "cannot take the address of an rvalue");
+}
+
+#[test]
+fn type_check_test() {
+    let errors = check(
+        "int f(int *p){int **pp; pp = p; return 0;} int g(){int a; int *p = a; return 0;} \
+         int *h(int *p){return 1;} int k(){int *p = 0; return p;} int m(){return f(1);} \
+         int n(){int a; return f(&a, a);}",
+    )
+    .unwrap_err();
+    assert_eq!(errors.len(), 6);
+    assert_eq!(errors[0].message, "incompatible pointer types");
+    assert_eq!(
+        errors[0].primary.message,
+        "expected `int **`, found `int *`"
+    );
+    assert_eq!(
+        errors[1].message,
+        "incompatible integer to pointer conversion"
+    );
+    assert_eq!(
+        errors[2].message,
+        "incompatible integer to pointer conversion"
+    );
+    assert_eq!(errors[2].notes, vec!["the function returns `int *`"]);
+    assert_eq!(
+        errors[3].message,
+        "incompatible pointer to integer conversion"
+    );
+    assert_eq!(
+        errors[4].message,
+        "incompatible integer to pointer conversion"
+    );
+    assert_eq!(errors[4].secondary[0].message, "defined here");
+    assert_eq!(
+        errors[5].message,
+        "`f` takes 1 argument but 2 were supplied"
+    );
+}
+
+#[test]
+fn call_type_test() {
+    let program =
+        check("int *id(int *p){return p;} int main(){int a[2]; int *p = id(a); return *id(&a[1]) + puts(p);}")
+            .unwrap();
+    let stmts = body(&program[1]);
+    let init = stmts[1].rhs.clone().unwrap();
+    assert_eq!(init.ty, Some(Type::PTR(Box::new(Type::INT))));
+}
+
+#[test]
+fn global_type_test() {
+    let errors =
+        check("int a; int *p = 0; int *q = 4; int b = &a; int **r = &p; int main(){return 0;}")
+            .unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(
+        errors[0].message,
+        "incompatible integer to pointer conversion"
+    );
+    assert_eq!(errors[0].primary.span.column, 29);
+    assert_eq!(
+        errors[1].message,
+        "incompatible pointer to integer conversion"
+    );
+    let errors = check(
+        "int a[3]; int *p = a; int *q = &a[1]; char *s = a; int **r = &a; int main(){return 0;}",
+    )
+    .unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec!["incompatible pointer types", "incompatible pointer types"]
+    );
+    assert_eq!(
+        errors[1].primary.message,
+        "expected `int **`, found `int (*)[3]`"
+    );
+}
+
+#[test]
+fn char_check_test() {
+    let program =
+        check("int main(){char c = 300; int i = c; char *p = &c; return *p + 'a';}").unwrap();
+    let stmts = body(&program[0]);
+    // promoted to `int` before the addition
+    let promoted = stmts[3].rhs.clone().unwrap().lhs.unwrap();
+    assert_eq!(promoted.ty, Some(Type::INT));
+    assert_eq!(promoted.rhs.unwrap().ty, Some(Type::CHAR));
+    let errors = check("int main(){char c; int *p = &c; return 0;}").unwrap_err();
+    assert_eq!(
+        errors[0].primary.message,
+        "expected `int *`, found `char *`"
+    );
+}
+
+#[test]
+fn string_check_test() {
+    let program =
+        check("char *g = \"x\"; int main(){char s[2] = \"ab\"; char *p = \"hi\"; return 0;}")
+            .unwrap();
+    let stmts = body(&program[0]);
+    assert_eq!(
+        stmts[1].rhs.clone().unwrap().ty,
+        Some(Type::ARRAY(Box::new(Type::CHAR), 3))
+    );
+    let errors =
+        check("int *g = \"x\"; int main(){char s[2] = \"abc\"; int a[3] = \"ab\"; return 0;}")
+            .unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].message, "incompatible pointer types");
+    assert_eq!(
+        errors[1].message,
+        "initializer-string for `char[2]` is too long"
+    );
+    let errors = check("int main(){int a[3] = \"ab\"; return 0;}").unwrap_err();
+    assert_eq!(
+        errors[0].message,
+        "cannot initialize `int[3]` with a string literal"
+    );
+}
+
+#[test]
+fn conversion_test() {
+    let program = check(
+        "long f(unsigned u, int i, short s, long l){u < i; s * s; l / i; 3000000000; return i;}",
+    )
+    .unwrap();
+    let stmts = body(&program[0]);
+    // `int` meets `unsigned int` as unsigned
+    let compare = stmts[0].clone();
+    assert_eq!(compare.ty, Some(Type::INT));
+    assert_eq!(compare.lhs.unwrap().kind, NodeKind::LVAL(4));
+    let rhs = compare.rhs.unwrap();
+    assert_eq!(rhs.kind, NodeKind::Cast);
+    assert_eq!(rhs.ty, Some(Type::UINT));
+    // both `short`s are promoted
+    let mul = stmts[1].clone();
+    assert_eq!(mul.ty, Some(Type::INT));
+    assert_eq!(mul.lhs.unwrap().kind, NodeKind::Cast);
+    assert_eq!(mul.rhs.unwrap().kind, NodeKind::Cast);
+    let div = stmts[2].clone();
+    assert_eq!(div.ty, Some(Type::LONG));
+    assert_eq!(div.rhs.unwrap().ty, Some(Type::LONG));
+    assert_eq!(stmts[3].ty, Some(Type::LONG));
+    let ret = stmts[4].rhs.clone().unwrap();
+    assert_eq!(ret.kind, NodeKind::Cast);
+    assert_eq!(ret.ty, Some(Type::LONG));
+}
+
+#[test]
+fn conversion_error_test() {
+    let program =
+        check("int f(int *p, int a[2], int (*g)(int)){p == 0; 0 != g; p < a; return p <= p;}");
+    assert!(program.is_ok());
+    let errors = check(
+        "int f(int *p){return p * 2;} int g(int *p){return 4 / p;} int h(int *p, char *s){return p == s;} \
+         int k(int *p, int a){return p < a;} int m(int *p){return 1 != p;}",
+    )
+    .unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "invalid operands to binary expression",
+            "invalid operands to binary expression",
+            "comparison of distinct pointer types",
+            "comparison between pointer and integer",
+            "comparison between pointer and integer",
+        ]
+    );
+    assert_eq!(errors[0].secondary[0].message, "`int *`");
+    assert_eq!(errors[2].secondary[1].message, "`char *`");
+}
+
+#[test]
+fn member_test() {
+    let program = check(
+        "struct s { char c; int *p; }; int main(){struct s a, *b; b = &a; a = *b; return b->c + *a.p;}",
+    )
+    .unwrap();
+    let stmts = body(&program[0]);
+    let add = stmts[4].rhs.clone().unwrap();
+    // `b->c` is `(*b).c`, promoted for the addition
+    let arrow = add.lhs.unwrap().rhs.unwrap();
+    assert_eq!(arrow.kind, NodeKind::Member("c".to_string()));
+    assert_eq!(arrow.ty, Some(Type::CHAR));
+    assert_eq!(arrow.lhs.unwrap().kind, NodeKind::Deref);
+    let errors = check(
+        "struct s { int x; }; struct t { int x; }; struct u; int f(struct u *p){return p->x;} int main(){struct s a; struct t b; int i; a = b; i.x; a.y; return a + 1;}",
+    )
+    .unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec!["incomplete definition of `struct u`", "incompatible types",]
+    );
+    assert_eq!(
+        errors[1].primary.message,
+        "expected `struct s`, found `struct t`"
+    );
+    let errors = check(
+        "struct s { int x; }; int f(){int i; return i.x;} int g(){struct s a; return a.y;} int h(){struct s a; return a + 1;}",
+    )
+    .unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "member reference base type `int` is not a struct",
+            "no member named `y` in `struct s`",
+            "invalid operands to binary expression",
+        ]
+    );
+}
+
+#[test]
+fn switch_check_test() {
+    let errors = check("int main(){int *p; switch (p) { case 0: ; } return 0;}").unwrap_err();
+    assert_eq!(errors[0].message, "switch condition is not an integer");
+    assert_eq!(errors[0].primary.message, "found `int *`");
+
+    // labels take the promoted type of the condition before they are compared
+    let program =
+        check("int main(){unsigned u; char c; switch (u) { case -1: ; } switch (c) { case 4294967297: ; } return 0;}")
+            .unwrap();
+    let stmts = body(&program[0]);
+    match &stmts[2].kind {
+        NodeKind::Switch { cases, .. } => assert_eq!(cases[0].0, 4294967295),
+        _ => panic!("expected switch"),
+    }
+    match &stmts[3].kind {
+        NodeKind::Switch { cases, .. } => assert_eq!(cases[0].0, 1),
+        _ => panic!("expected switch"),
+    }
+    let condition = stmts[3].lhs.clone().unwrap();
+    assert_eq!(condition.kind, NodeKind::Cast);
+    assert_eq!(condition.ty, Some(Type::INT));
+
+    let errors = check(
+        "int f(int x){switch (x) { case 1: ; case 1: ; } return 0;} int g(unsigned u){switch (u) { case 4294967295: ; case -1: ; } return 0;}",
+    )
+    .unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "duplicate case value `1`",
+            "duplicate case value `4294967295`"
+        ]
+    );
+    assert_eq!(errors[1].secondary[0].message, "previous case here");
+}
+
+#[test]
+fn cast_check_test() {
+    let program = check("int main(){int a[2]; long l; l = (long)a; return (char)l;}").unwrap();
+    let stmts = body(&program[0]);
+    // an array decays before it is cast
+    let cast = stmts[2].rhs.clone().unwrap();
+    assert_eq!(cast.ty, Some(Type::LONG));
+    let ret = stmts[3].rhs.clone().unwrap();
+    assert_eq!(ret.kind, NodeKind::Cast);
+    assert_eq!(ret.ty, Some(Type::INT));
+    let errors = check(
+        "struct s { int a; }; int f(){struct s v; return (int)v;} int g(){int *p; (struct s)p;}",
+    )
+    .unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "cannot cast `struct s` to `int`",
+            "cannot cast `int *` to `struct s`",
+        ]
+    );
+}
+
+#[test]
+fn function_pointer_check_test() {
+    let program = check(
+        "long f(long a){return a;} int main(){long (*p)(long) = f; char c; p = &f; return p(c);}",
+    )
+    .unwrap();
+    let stmts = body(&program[1]);
+    // the `long` result is cast to the `int` returned
+    let call = stmts[3].rhs.clone().unwrap().rhs.unwrap();
+    assert_eq!(call.ty, Some(Type::LONG));
+    // the argument converts to the parameter type of the pointed-to function
+    if let NodeKind::Call(argv) = call.kind {
+        assert_eq!(argv[0].kind, NodeKind::Cast);
+        assert_eq!(argv[0].ty, Some(Type::LONG));
+    } else {
+        panic!("expected an indirect call");
+    }
+    let errors = check(
+        "int f(int a){return a;} int g(){int x; return x(1);} int h(){int (*p)(int) = f; return p();} int k(){f = 0;}",
+    )
+    .unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "called object type `int` is not a function or function pointer",
+            "function of type `int (int)` takes 1 argument but 0 were supplied",
+            "cannot assign to a function",
+        ]
+    );
+}
+
+#[test]
+fn variadic_check_test() {
+    let program = check(
+        "int printf(char *fmt, ...); int main(){char c; short s; long l; return printf(\"%d\", c, s, l);}",
+    )
+    .unwrap();
+    let stmts = body(&program[0]);
+    if let NodeKind::Func { argv, .. } = stmts[3].rhs.clone().unwrap().kind {
+        // arguments past the parameters only go through the promotions
+        let types: Vec<Option<Type>> = argv.iter().map(|arg| arg.ty.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                Some(Type::ARRAY(Box::new(Type::CHAR), 3)),
+                Some(Type::INT),
+                Some(Type::INT),
+                Some(Type::LONG),
+            ]
+        );
+    } else {
+        panic!("expected a call");
+    }
+    let errors = check(
+        "int printf(char *fmt, ...); int f(int); int g(){return printf();} int h(){return f(\"x\");}",
+    )
+    .unwrap_err();
+    assert_eq!(
+        errors[0].message,
+        "`printf` takes at least 1 argument but 0 were supplied"
+    );
+    assert_eq!(
+        errors[1].message,
+        "incompatible pointer to integer conversion"
+    );
+    // `()` takes whatever it is given, and `(void)` nothing
+    let program = check("int f(); int main(){int (*p)(int) = f; char c; return f(c, 2);}").unwrap();
+    let stmts = body(&program[0]);
+    if let NodeKind::Func { argv, .. } = stmts[2].rhs.clone().unwrap().kind {
+        assert_eq!(argv[0].ty, Some(Type::INT));
+    } else {
+        panic!("expected a call");
+    }
+    let errors = check("int f(void); int g(){return f(1);}").unwrap_err();
+    assert_eq!(
+        errors[0].message,
+        "`f` takes 0 arguments but 1 was supplied"
+    );
+}
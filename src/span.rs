@@ -0,0 +1,114 @@
+// A region of source text: the file it belongs to, the byte range it covers
+// and the (1-based) line and column where it starts.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub(crate) struct Span {
+    pub(crate) file: usize,
+    pub(crate) offset: usize, // byte offset from the start of the file
+    pub(crate) len: usize,    // length in bytes
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+impl Span {
+    pub(crate) fn new(file: usize, offset: usize, len: usize, line: usize, column: usize) -> Self {
+        Self {
+            file,
+            offset,
+            len,
+            line,
+            column,
+        }
+    }
+
+    pub(crate) fn end(&self) -> usize {
+        self.offset + self.len
+    }
+
+    // the smallest span covering both `self` and `other`
+    pub(crate) fn to(self, other: Span) -> Span {
+        if self.file != other.file {
+            return self;
+        }
+        let (first, second) = if self.offset <= other.offset {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let end = first.end().max(second.end());
+        Span {
+            len: end - first.offset,
+            ..first
+        }
+    }
+
+    // an empty span right after `self`, used to point at missing tokens
+    pub(crate) fn after(self) -> Span {
+        Span {
+            offset: self.end(),
+            len: 0,
+            column: self.column + self.len,
+            ..self
+        }
+    }
+}
+
+// A source file registered with the compiler; spans refer to it by index.
+pub(crate) struct SourceFile {
+    pub(crate) name: String,
+    pub(crate) src: String,
+}
+
+impl SourceFile {
+    // the text of a (1-based) line, without the line break
+    pub(crate) fn line(&self, line: usize) -> Option<&str> {
+        self.src.lines().nth(line.checked_sub(1)?)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    // register a file and return its id
+    pub(crate) fn add(&mut self, name: String, src: String) -> usize {
+        self.files.push(SourceFile { name, src });
+        self.files.len() - 1
+    }
+
+    pub(crate) fn get(&self, file: usize) -> Option<&SourceFile> {
+        self.files.get(file)
+    }
+}
+
+//------------------------------------------------------------------------
+
+#[cfg(test)]
+#[test]
+fn to_test() {
+    let a = Span::new(0, 4, 2, 1, 5);
+    let b = Span::new(0, 10, 3, 2, 1);
+    assert_eq!(a.to(b), Span::new(0, 4, 9, 1, 5));
+    assert_eq!(b.to(a), Span::new(0, 4, 9, 1, 5));
+}
+
+#[test]
+fn after_test() {
+    let a = Span::new(0, 4, 2, 1, 5);
+    assert_eq!(a.after(), Span::new(0, 6, 0, 1, 7));
+}
+
+#[test]
+fn source_map_test() {
+    let mut sources = SourceMap::new();
+    let id = sources.add("a.c".to_string(), "int a;\nint b;\n".to_string());
+    let file = sources.get(id).unwrap();
+    assert_eq!(file.line(2), Some("int b;"));
+    assert_eq!(file.line(0), None);
+    assert_eq!(file.line(3), None);
+}
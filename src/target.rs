@@ -0,0 +1,308 @@
+// The platform the generated assembly is for. Both are x86-64 with the
+// System V calling convention; they differ in object format (ELF vs Mach-O),
+// which shows up in symbol names and assembler directives.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Target {
+    LinuxGnu,    // x86_64-linux-gnu
+    AppleDarwin, // x86_64-apple-darwin
+}
+
+impl Target {
+    pub(crate) fn parse(triple: &str) -> Option<Self> {
+        match triple {
+            "x86_64-linux-gnu" | "x86_64-unknown-linux-gnu" => Some(Target::LinuxGnu),
+            "x86_64-apple-darwin" => Some(Target::AppleDarwin),
+            _ => None,
+        }
+    }
+
+    // the platform rcc itself runs on
+    pub(crate) fn host() -> Self {
+        if cfg!(target_os = "macos") {
+            Target::AppleDarwin
+        } else {
+            Target::LinuxGnu
+        }
+    }
+
+    pub(crate) fn triple(&self) -> &'static str {
+        match self {
+            Target::LinuxGnu => "x86_64-linux-gnu",
+            Target::AppleDarwin => "x86_64-apple-darwin",
+        }
+    }
+
+    // the assembler name of a C identifier; Mach-O prefixes an underscore
+    pub(crate) fn symbol(&self, name: &str) -> String {
+        match self {
+            Target::LinuxGnu => name.to_string(),
+            Target::AppleDarwin => format!("_{}", name),
+        }
+    }
+
+    // a `call` to a named function; on ELF go through the PLT so calls into
+    // shared libraries link in position independent code
+    pub(crate) fn call(&self, name: &str) -> Vec<String> {
+        let line = match self {
+            Target::LinuxGnu => format!("  call {}@PLT", name),
+            Target::AppleDarwin => format!("  call {}", self.symbol(name)),
+        };
+        self.escape(name, line.clone(), line)
+    }
+
+    // `intel`, a line mentioning the symbol for `name`, or `att`, the same
+    // line in AT&T syntax: in Intel syntax GAS takes names such as `dx` or
+    // `si` for registers, in any case, while in AT&T syntax a register is
+    // always written `%dx`. Mach-O symbols start with an underscore and
+    // never clash
+    fn escape(&self, name: &str, intel: String, att: String) -> Vec<String> {
+        match self {
+            Target::LinuxGnu if is_reserved(name) => {
+                vec![
+                    ".att_syntax".to_string(),
+                    att,
+                    ".intel_syntax noprefix".to_string(),
+                ]
+            }
+            _ => vec![intel],
+        }
+    }
+
+    // load the address of a symbol in this object into rax
+    pub(crate) fn address(&self, name: &str) -> Vec<String> {
+        let symbol = self.symbol(name);
+        self.escape(
+            name,
+            format!("  lea rax, [rip + {}]", symbol),
+            format!("  leaq {}(%rip), %rax", symbol),
+        )
+    }
+
+    // load the address of a function from another object into rax, from
+    // its GOT slot; it may be in a shared library out of reach of a `lea`
+    pub(crate) fn got(&self, name: &str) -> Vec<String> {
+        let symbol = self.symbol(name);
+        self.escape(
+            name,
+            format!("  mov rax, [rip + {}@GOTPCREL]", symbol),
+            format!("  movq {}@GOTPCREL(%rip), %rax", symbol),
+        )
+    }
+
+    // a pointer-sized word holding the address `offset` bytes into a symbol
+    pub(crate) fn quad(&self, name: &str, offset: i64) -> Vec<String> {
+        let line = match offset {
+            0 => format!("  .quad {}", self.symbol(name)),
+            _ => format!("  .quad {}{:+}", self.symbol(name), offset),
+        };
+        self.escape(name, line.clone(), line)
+    }
+
+    pub(crate) fn text_section(&self) -> &'static str {
+        ".text"
+    }
+
+    pub(crate) fn data_section(&self) -> &'static str {
+        ".data"
+    }
+
+    pub(crate) fn bss_section(&self) -> &'static str {
+        ".bss"
+    }
+
+    pub(crate) fn rodata_section(&self) -> &'static str {
+        match self {
+            Target::LinuxGnu => ".section .rodata",
+            Target::AppleDarwin => ".section __TEXT,__const",
+        }
+    }
+
+    // directives before a variable's label
+    pub(crate) fn object_header(&self, name: &str) -> Vec<String> {
+        let symbol = self.symbol(name);
+        let mut lines = vec![format!(".globl {}", symbol)];
+        if let Target::LinuxGnu = self {
+            lines.push(format!(".type {}, @object", symbol));
+        }
+        lines
+    }
+
+    // directives after a variable's storage
+    pub(crate) fn object_footer(&self, name: &str, size: usize) -> Vec<String> {
+        match self {
+            Target::LinuxGnu => vec![format!(".size {}, {}", self.symbol(name), size)],
+            Target::AppleDarwin => Vec::new(),
+        }
+    }
+
+    // directives before a function's label
+    pub(crate) fn function_header(&self, name: &str) -> Vec<String> {
+        let symbol = self.symbol(name);
+        let mut lines = vec![format!(".globl {}", symbol)];
+        if let Target::LinuxGnu = self {
+            lines.push(format!(".type {}, @function", symbol));
+        }
+        lines
+    }
+
+    // directives after a function's last instruction
+    pub(crate) fn function_footer(&self, name: &str) -> Vec<String> {
+        match self {
+            Target::LinuxGnu => {
+                let symbol = self.symbol(name);
+                let line = format!(".size {}, .-{}", symbol, symbol);
+                self.escape(name, line.clone(), line)
+            }
+            Target::AppleDarwin => Vec::new(),
+        }
+    }
+
+    // directives closing the file
+    pub(crate) fn file_footer(&self) -> Vec<String> {
+        match self {
+            // we never need an executable stack
+            Target::LinuxGnu => vec![".section .note.GNU-stack,\"\",@progbits".to_string()],
+            Target::AppleDarwin => vec![".subsections_via_symbols".to_string()],
+        }
+    }
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target::host()
+    }
+}
+
+// names GAS reads as a register or an operator in Intel syntax, besides
+// the numbered registers below
+const RESERVED: &[&str] = &[
+    "al", "ah", "ax", "axl", "eax", "rax", "bl", "bh", "bx", "bxl", "ebx", "rbx", "cl", "ch", "cx",
+    "cxl", "ecx", "rcx", "dl", "dh", "dx", "dxl", "edx", "rdx", "sil", "si", "esi", "rsi", "dil",
+    "di", "edi", "rdi", "bpl", "bp", "ebp", "rbp", "spl", "sp", "esp", "rsp", "ip", "eip", "rip",
+    "cs", "ds", "es", "fs", "gs", "ss", "st", "and", "or", "xor", "not", "mod", "shl", "shr", "eq",
+    "ne", "lt", "le", "gt", "ge", "flat", "short", "offset",
+];
+
+// `r8d`, `xmm15` or `k1`, up to any number
+const NUMBERED: &[&str] = &[
+    "r", "mm", "xmm", "ymm", "zmm", "k", "tmm", "bnd", "cr", "dr", "db", "tr", "st",
+];
+
+// would GAS take `name` for something other than a symbol in Intel
+// syntax? errs on the side of yes, which only costs a syntax switch
+fn is_reserved(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    if RESERVED.contains(&name.as_str()) {
+        return true;
+    }
+    let body = match name.strip_suffix(['b', 'w', 'd', 'l']) {
+        Some(body) if body.starts_with('r') => body,
+        _ => &name,
+    };
+    let prefix = body.trim_end_matches(|c: char| c.is_ascii_digit());
+    prefix.len() < body.len() && NUMBERED.contains(&prefix)
+}
+
+//------------------------------------------------------------------------
+
+#[cfg(test)]
+#[test]
+fn parse_test() {
+    assert_eq!(Target::parse("x86_64-linux-gnu"), Some(Target::LinuxGnu));
+    assert_eq!(
+        Target::parse("x86_64-apple-darwin"),
+        Some(Target::AppleDarwin)
+    );
+    assert_eq!(Target::parse("riscv64"), None);
+    assert_eq!(
+        Target::parse(Target::LinuxGnu.triple()),
+        Some(Target::LinuxGnu)
+    );
+}
+
+#[test]
+fn symbol_test() {
+    assert_eq!(Target::LinuxGnu.symbol("main"), "main");
+    assert_eq!(Target::AppleDarwin.symbol("main"), "_main");
+    assert_eq!(Target::LinuxGnu.call("puts"), vec!["  call puts@PLT"]);
+    assert_eq!(Target::AppleDarwin.call("puts"), vec!["  call _puts"]);
+    assert_eq!(
+        Target::LinuxGnu.got("puts"),
+        vec!["  mov rax, [rip + puts@GOTPCREL]"]
+    );
+    assert_eq!(
+        Target::AppleDarwin.got("puts"),
+        vec!["  mov rax, [rip + _puts@GOTPCREL]"]
+    );
+}
+
+#[test]
+fn function_test() {
+    assert_eq!(
+        Target::LinuxGnu.function_header("foo"),
+        vec![".globl foo", ".type foo, @function"]
+    );
+    assert_eq!(
+        Target::LinuxGnu.function_footer("foo"),
+        vec![".size foo, .-foo"]
+    );
+    assert_eq!(
+        Target::AppleDarwin.function_header("foo"),
+        vec![".globl _foo"]
+    );
+    assert!(Target::AppleDarwin.function_footer("foo").is_empty());
+}
+
+#[test]
+fn reserved_test() {
+    for name in ["si", "DX", "r8d", "xmm15", "k1", "offset", "and"] {
+        assert!(is_reserved(name), "{}", name);
+    }
+    for name in ["main", "sum", "dx2", "r8x", "xmm", "k"] {
+        assert!(!is_reserved(name), "{}", name);
+    }
+    // in AT&T syntax around the name, back to Intel after it
+    assert_eq!(
+        Target::LinuxGnu.call("si"),
+        vec![".att_syntax", "  call si@PLT", ".intel_syntax noprefix"]
+    );
+    assert_eq!(
+        Target::LinuxGnu.function_footer("si"),
+        vec![".att_syntax", ".size si, .-si", ".intel_syntax noprefix"]
+    );
+    assert_eq!(Target::AppleDarwin.call("si"), vec!["  call _si"]);
+    assert_eq!(
+        Target::LinuxGnu.address("dx"),
+        vec![
+            ".att_syntax",
+            "  leaq dx(%rip), %rax",
+            ".intel_syntax noprefix"
+        ]
+    );
+    assert_eq!(Target::LinuxGnu.address("x"), vec!["  lea rax, [rip + x]"]);
+    assert_eq!(Target::LinuxGnu.quad("a", -4), vec!["  .quad a-4"]);
+    assert_eq!(Target::AppleDarwin.quad("a", 8), vec!["  .quad _a+8"]);
+    assert_eq!(
+        Target::LinuxGnu.got("gs"),
+        vec![
+            ".att_syntax",
+            "  movq gs@GOTPCREL(%rip), %rax",
+            ".intel_syntax noprefix"
+        ]
+    );
+    assert_eq!(
+        Target::LinuxGnu.quad("dx", 0),
+        vec![".att_syntax", "  .quad dx", ".intel_syntax noprefix"]
+    );
+}
+
+#[test]
+fn object_test() {
+    assert_eq!(
+        Target::LinuxGnu.object_header("x"),
+        vec![".globl x", ".type x, @object"]
+    );
+    assert_eq!(Target::LinuxGnu.object_footer("x", 8), vec![".size x, 8"]);
+    assert_eq!(Target::AppleDarwin.object_header("x"), vec![".globl _x"]);
+    assert!(Target::AppleDarwin.object_footer("x", 8).is_empty());
+}